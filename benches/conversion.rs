@@ -0,0 +1,115 @@
+//! `cargo bench` runs each conversion stage on a synthetic log, decoding both on one thread and with the default jobs.
+//! For real logs, use `arducap bench <log.bin>`.
+
+use std::time::Duration;
+
+use arducap::{
+    bench::{count_messages, decode_frames, payload_bytes, transform_frames, write_mcap},
+    parallel::{decoding_jobs, default_jobs},
+    pipeline::default_transformers,
+    reader::LogValue::{Float, Int, UInt},
+    testing::LogBuilder,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const GPS: u8 = 10;
+const ATT: u8 = 11;
+const IMU: u8 = 12;
+
+// roughly a 10 minute flight: GPS at 10Hz, ATT at 50Hz, IMU at 400Hz.
+fn synthetic_log() -> Vec<u8> {
+    let mut log = LogBuilder::new();
+    log.definition(GPS, "GPS", "QBLLif", "TimeUS,Status,Lat,Lng,Alt,Spd")
+        .definition(
+            ATT,
+            "ATT",
+            "Qccccc",
+            "TimeUS,DesRoll,Roll,DesPitch,Pitch,Yaw",
+        )
+        .definition(
+            IMU,
+            "IMU",
+            "Qffffff",
+            "TimeUS,GyrX,GyrY,GyrZ,AccX,AccY,AccZ",
+        );
+
+    for tick in 0..600 * 400u64 {
+        let ts = 2_500 * tick;
+        let t = tick as f64 / 400.0;
+
+        let imu = [0.01 * t.sin(), 0.02, -0.01, 0.1, -0.2, -9.81];
+        let mut values = vec![UInt(ts)];
+        values.extend(imu.iter().map(|&v| Float(v as f32)));
+        log.message(IMU, &values);
+
+        if tick % 8 == 0 {
+            let angle = (1000.0 * t.sin()) as i64;
+            log.message(
+                ATT,
+                &[
+                    UInt(ts),
+                    Int(angle),
+                    Int(angle + 5),
+                    Int(-angle),
+                    Int(-angle - 5),
+                    Int(9000),
+                ],
+            );
+        }
+
+        if tick % 40 == 0 {
+            log.message(
+                GPS,
+                &[
+                    UInt(ts),
+                    UInt(3),
+                    Int(473977420 + tick as i64),
+                    Int(85455940 - tick as i64),
+                    Int(48800),
+                    Float(5.0),
+                ],
+            );
+        }
+    }
+
+    log.build()
+}
+
+fn conversion_stages(c: &mut Criterion) {
+    let log = synthetic_log();
+    let frames = decode_frames(&log, 1).unwrap();
+    let transformed = transform_frames(&frames, default_transformers()).unwrap();
+
+    let mut jobs = vec![1, decoding_jobs(default_jobs())];
+    jobs.dedup();
+
+    let mut group = c.benchmark_group("decode");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(log.len() as u64));
+    for jobs in jobs {
+        group.bench_with_input(BenchmarkId::new("jobs", jobs), &jobs, |b, &jobs| {
+            b.iter_with_large_drop(|| decode_frames(&log, jobs).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("transform");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(10));
+    group.throughput(Throughput::Elements(count_messages(&frames)));
+    group.bench_function("default_transformers", |b| {
+        b.iter_with_large_drop(|| transform_frames(&frames, default_transformers()).unwrap())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("write");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(payload_bytes(&transformed)));
+    group.bench_function("mcap", |b| {
+        b.iter_with_large_drop(|| write_mcap(&transformed).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, conversion_stages);
+criterion_main!(benches);
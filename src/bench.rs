@@ -0,0 +1,163 @@
+//! Measures the conversion stages (decode, transform, write) separately, so that performance regressions
+//! can be pinned on a stage. Every stage runs to completion before the next one starts,
+//! so the whole log, decoded and transformed, is held in memory.
+
+use std::{
+    fmt,
+    io::Cursor,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{
+    parallel::{decoding_jobs, read_frames_parallel},
+    pipeline::{McapSink, TransformerSet},
+    reader::{ArduFrame, ArduReader},
+    transformers::{TransformedMessage, Transformer},
+};
+
+pub struct BenchReport {
+    /// threads that actually decoded, see `decoding_jobs`.
+    pub jobs: usize,
+    pub input_bytes: u64,
+    pub input_messages: u64,
+    pub decode_time: Duration,
+    pub output_messages: u64,
+    pub transform_time: Duration,
+    pub output_bytes: u64,
+    pub write_time: Duration,
+}
+
+fn rate(amount: u64, time: Duration) -> f64 {
+    amount as f64 / time.as_secs_f64().max(f64::EPSILON)
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MB: f64 = 1024.0 * 1024.0;
+
+        writeln!(
+            f,
+            "decode    ({} jobs): {:>9.3?}  {:>9.1} MB/s  {:>11.0} msgs/s  ({} messages in, {:.1} MB)",
+            self.jobs,
+            self.decode_time,
+            rate(self.input_bytes, self.decode_time) / MB,
+            rate(self.input_messages, self.decode_time),
+            self.input_messages,
+            self.input_bytes as f64 / MB,
+        )?;
+        writeln!(
+            f,
+            "transform          : {:>9.3?}  {:>9.1} MB/s  {:>11.0} msgs/s  ({} messages out)",
+            self.transform_time,
+            rate(self.input_bytes, self.transform_time) / MB,
+            rate(self.input_messages, self.transform_time),
+            self.output_messages,
+        )?;
+        write!(
+            f,
+            "write              : {:>9.3?}  {:>9.1} MB/s  {:>11.0} msgs/s  ({:.1} MB of payloads)",
+            self.write_time,
+            rate(self.output_bytes, self.write_time) / MB,
+            rate(self.output_messages, self.write_time),
+            self.output_bytes as f64 / MB,
+        )
+    }
+}
+
+/// Stage 1: decodes a whole in-memory log, on `jobs` threads if more than one. The last frame is `ArduFrame::Eof`.
+pub fn decode_frames(log: &[u8], jobs: usize) -> Result<Vec<ArduFrame>> {
+    let mut frames = Vec::new();
+    if jobs > 1 {
+        read_frames_parallel(log, jobs, |frame| {
+            frames.push(frame);
+            Ok(())
+        })?;
+    } else {
+        let mut reader = ArduReader::from_reader(Cursor::new(log));
+        loop {
+            let frame = reader.read()?;
+            let is_eof = matches!(frame, ArduFrame::Eof);
+            frames.push(frame);
+            if is_eof {
+                break;
+            }
+        }
+    }
+    Ok(frames)
+}
+
+/// Stage 2: runs decoded frames through the transformers, keeping the log time of each output message.
+pub fn transform_frames(
+    frames: &[ArduFrame],
+    transformers: Vec<Box<dyn Transformer>>,
+) -> Result<Vec<(u64, TransformedMessage)>> {
+    let mut transformer_set = TransformerSet::new(transformers);
+    let mut transformed = Vec::new();
+    for frame in frames {
+        let log_time = match frame {
+            ArduFrame::ArduMessage(message) => message.current_ts,
+            _ => 0,
+        };
+        for out_msg in transformer_set.transform_frame(frame)? {
+            transformed.push((log_time, out_msg));
+        }
+    }
+    Ok(transformed)
+}
+
+/// Stage 3: writes transformed messages to an in-memory MCAP, returning the file contents.
+pub fn write_mcap(transformed: &[(u64, TransformedMessage)]) -> Result<Vec<u8>> {
+    let mut sink = McapSink::new(Cursor::new(Vec::new()))?;
+    for (log_time, out_msg) in transformed {
+        sink.write(out_msg, *log_time)?;
+    }
+    sink.finish()?;
+    Ok(sink.into_inner().into_inner())
+}
+
+/// Converts an in-memory log to an in-memory MCAP, timing each stage once.
+/// Transform rates are relative to the decoded input, write rates to the transformed payloads.
+pub fn run_benchmark(
+    log: &[u8],
+    jobs: usize,
+    transformers: Vec<Box<dyn Transformer>>,
+) -> Result<BenchReport> {
+    let start = Instant::now();
+    let frames = decode_frames(log, jobs)?;
+    let decode_time = start.elapsed();
+
+    let start = Instant::now();
+    let transformed = transform_frames(&frames, transformers)?;
+    let transform_time = start.elapsed();
+
+    let start = Instant::now();
+    write_mcap(&transformed)?;
+    let write_time = start.elapsed();
+
+    Ok(BenchReport {
+        jobs: decoding_jobs(jobs),
+        input_bytes: log.len() as u64,
+        input_messages: count_messages(&frames),
+        decode_time,
+        output_messages: transformed.len() as u64,
+        transform_time,
+        output_bytes: payload_bytes(&transformed),
+        write_time,
+    })
+}
+
+pub fn count_messages(frames: &[ArduFrame]) -> u64 {
+    frames
+        .iter()
+        .filter(|f| matches!(f, ArduFrame::ArduMessage(_)))
+        .count() as u64
+}
+
+pub fn payload_bytes(transformed: &[(u64, TransformedMessage)]) -> u64 {
+    transformed
+        .iter()
+        .map(|(_, m)| m.payload.len() as u64)
+        .sum()
+}
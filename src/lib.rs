@@ -1,3 +1,9 @@
+pub mod bench;
+pub mod mapping;
+pub mod parallel;
 pub mod pipeline;
 pub mod reader;
+pub mod segments;
+pub mod table;
+pub mod testing;
 pub mod transformers;
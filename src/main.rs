@@ -1,18 +1,128 @@
-use std::env;
+use std::{collections::HashSet, env, fs, path::Path};
 
-use anyhow::Result;
-use arducap::pipeline::process_ardupilot_file;
+use anyhow::{anyhow, Result};
+use arducap::{
+    bench::run_benchmark,
+    mapping::MappingTransformer,
+    parallel::default_jobs,
+    pipeline::{check_log_start, default_transformers, process_ardupilot_segments_with},
+    segments::find_continuation_segments,
+    transformers::Transformer,
+};
+
+fn print_usage(program: &str) {
+    eprintln!(
+        "Usage: {} [-j <jobs>] [--mapping <mapping.json>]... [--concat] <logfile.bin>... ",
+        program
+    );
+    eprintln!("       {} bench [-j <jobs>] <logfile.bin>... ", program);
+}
 
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        eprintln!("Usage: {} <logfile.bin>... ", args[0]);
+        print_usage(&args[0]);
+        return Ok(());
+    }
+
+    let is_bench = args[1] == "bench";
+    let first_arg = if is_bench { 2 } else { 1 };
+
+    let mut jobs = default_jobs();
+    let mut mapping_files = Vec::new();
+    let mut concat = false;
+    let mut filenames = Vec::new();
+
+    let mut rest = args[first_arg..].iter();
+    while let Some(arg) = rest.next() {
+        if arg == "-j" || arg == "--jobs" {
+            jobs = rest
+                .next()
+                .and_then(|n| n.parse().ok())
+                .filter(|&n| n > 0)
+                .ok_or_else(|| anyhow!("{} expects a positive number of jobs", arg))?;
+        } else if is_bench && ["--concat", "-m", "--mapping"].contains(&arg.as_str()) {
+            return Err(anyhow!("{} isn't supported by bench", arg));
+        } else if arg == "--concat" {
+            concat = true;
+        } else if arg == "-m" || arg == "--mapping" {
+            let mapping_file = rest
+                .next()
+                .ok_or_else(|| anyhow!("{} expects a mapping file", arg))?;
+            // parse early, so that a broken mapping file fails before any conversion.
+            MappingTransformer::from_file(mapping_file)?;
+            mapping_files.push(mapping_file);
+        } else {
+            filenames.push(arg);
+        }
+    }
+
+    if filenames.is_empty() {
+        print_usage(&args[0]);
         return Ok(());
     }
 
-    for filename in &args[1..] {
-        process_ardupilot_file(filename)?;
+    if is_bench {
+        for filename in filenames {
+            let log = fs::read(filename)?;
+            println!("{}:", filename);
+            println!("{}", run_benchmark(&log, jobs, default_transformers())?);
+        }
+        return Ok(());
+    }
+
+    // with --concat, all files are segments of one log, in the given order.
+    // Otherwise, each log picks up the numbered siblings that continue it, if any. Segments are looked up for all
+    // files first, so that a file continuing another one on the command line is skipped, whatever the order.
+    let logs: Vec<Vec<String>> = if concat {
+        vec![filenames.into_iter().cloned().collect()]
+    } else {
+        let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        let mut logs = Vec::new();
+        let mut continuations = HashSet::new();
+        for filename in filenames {
+            let mut segments = vec![filename.clone()];
+            for segment in find_continuation_segments(filename)? {
+                continuations.insert(canonical(&segment));
+                segments.push(segment.display().to_string());
+            }
+            logs.push(segments);
+        }
+
+        logs.retain(|segments| {
+            let is_continuation = continuations.contains(&canonical(Path::new(&segments[0])));
+            if is_continuation {
+                eprintln!(
+                    "Skipping {}, it is converted along with the log it continues",
+                    segments[0]
+                );
+            }
+            !is_continuation
+        });
+        logs
+    };
+
+    // fail before writing anything.
+    for segments in &logs {
+        check_log_start(&segments[0])?;
+    }
+
+    for segments in logs {
+        if segments.len() > 1 {
+            eprintln!("Concatenating segments: {}", segments.join(", "));
+        }
+
+        let mut transformers = default_transformers();
+        for mapping_file in &mapping_files {
+            let mapping: Box<dyn Transformer> =
+                Box::new(MappingTransformer::from_file(mapping_file)?);
+            transformers.push(mapping);
+        }
+
+        let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+        process_ardupilot_segments_with(&segments, jobs, transformers)?;
     }
 
     Ok(())
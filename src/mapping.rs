@@ -0,0 +1,420 @@
+//! A generic transformer, driven by a JSON mapping file, for creating new topics out of niche messages
+//! without writing Rust. E.g.
+//!
+//! ```json
+//! {
+//!   "mappings": [
+//!     {
+//!       "message": "BARO",
+//!       "topic": "/custom/baro",
+//!       "schema": "custom.Baro",
+//!       "fields": {
+//!         "timestamp": "$timestamp",
+//!         "frame_id": { "value": "base_link" },
+//!         "altitude": "Alt",
+//!         "pressure.hpa": { "field": "Press", "scale": 0.01 },
+//!         "temperature.kelvin": { "field": "Temp", "offset": 273.15 }
+//!       }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Output keys with dots create nested objects. A field is either a label of the message, `"$timestamp"`
+//! (the message time, as `{ "sec", "nsec" }`), a label with an optional `scale` and `offset` (applied as
+//! `value * scale + offset`), or a constant `value`. The JSON schema of each topic is generated from its fields.
+
+use std::{collections::HashMap, fs};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    reader::{ArduDefinition, ArduMessage, LogValue},
+    transformers::{TransformedMessage, Transformer},
+};
+
+const TIMESTAMP_FIELD: &str = "$timestamp";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingFile {
+    mappings: Vec<Mapping>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Mapping {
+    message: String,
+    topic: String,
+    schema: String,
+    fields: Map<String, Value>,
+}
+
+#[derive(Debug)]
+enum FieldSource {
+    Timestamp,
+    Label {
+        label: String,
+        scale: f64,
+        offset: f64,
+    },
+    Constant(Value),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LabelSpec {
+    field: String,
+    #[serde(default = "one")]
+    scale: f64,
+    #[serde(default)]
+    offset: f64,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConstantSpec {
+    value: Value,
+}
+
+impl FieldSource {
+    fn parse(output_key: &str, spec: &Value) -> Result<Self> {
+        let context = || format!("invalid mapping for output field \"{}\"", output_key);
+
+        match spec {
+            Value::String(s) if s == TIMESTAMP_FIELD => Ok(FieldSource::Timestamp),
+            Value::String(s) => Ok(FieldSource::Label {
+                label: s.clone(),
+                scale: 1.0,
+                offset: 0.0,
+            }),
+            Value::Object(obj) if obj.contains_key("value") => {
+                let spec: ConstantSpec =
+                    serde_json::from_value(spec.clone()).with_context(context)?;
+                Ok(FieldSource::Constant(spec.value))
+            }
+            Value::Object(_) => {
+                let spec: LabelSpec = serde_json::from_value(spec.clone()).with_context(context)?;
+                Ok(FieldSource::Label {
+                    label: spec.field,
+                    scale: spec.scale,
+                    offset: spec.offset,
+                })
+            }
+            _ => Err(anyhow!(
+                "{}: expected a label, \"{}\", or an object",
+                context(),
+                TIMESTAMP_FIELD
+            )),
+        }
+    }
+}
+
+struct CompiledMapping {
+    message: String,
+    topic: String,
+    schema_name: String,
+    fields: Vec<(Vec<String>, FieldSource)>,
+    // generated on registration, once the field types are known
+    schema_data: Vec<u8>,
+}
+
+pub struct MappingTransformer {
+    mappings: Vec<CompiledMapping>,
+    subscriptions: HashMap<u8, Vec<usize>>,
+}
+
+impl MappingTransformer {
+    pub fn from_json(json_str: &str) -> Result<Self> {
+        let file: MappingFile = serde_json::from_str(json_str)?;
+
+        let mut mappings = Vec::new();
+        for mapping in file.mappings {
+            let mut fields = Vec::new();
+            for (output_key, spec) in &mapping.fields {
+                let path: Vec<String> = output_key.split('.').map(|s| s.to_string()).collect();
+                if path.iter().any(|p| p.is_empty()) {
+                    return Err(anyhow!("invalid output field \"{}\"", output_key));
+                }
+                fields.push((path, FieldSource::parse(output_key, spec)?));
+            }
+
+            // "a" and "a.b" can't both be set, "a" would have to be a value and an object at the same time.
+            for (path, _) in &fields {
+                for (other, _) in &fields {
+                    if other.len() > path.len() && other.starts_with(path) {
+                        return Err(anyhow!(
+                            "output fields \"{}\" and \"{}\" conflict",
+                            path.join("."),
+                            other.join(".")
+                        ));
+                    }
+                }
+            }
+
+            mappings.push(CompiledMapping {
+                message: mapping.message,
+                topic: mapping.topic,
+                schema_name: mapping.schema,
+                fields,
+                schema_data: Vec::new(),
+            });
+        }
+
+        Ok(Self {
+            mappings,
+            subscriptions: HashMap::new(),
+        })
+    }
+
+    pub fn from_file(filename: &str) -> Result<Self> {
+        let json_str = fs::read_to_string(filename)
+            .with_context(|| format!("Failed reading mapping file {}", filename))?;
+        Self::from_json(&json_str).with_context(|| format!("Invalid mapping file {}", filename))
+    }
+}
+
+// inserts `value` at `path`, creating intermediate objects along the way.
+fn insert_at(obj: &mut Map<String, Value>, path: &[String], value: Value) {
+    let (last, parents) = path.split_last().unwrap();
+
+    let mut current = obj;
+    for key in parents {
+        // conflicting paths are rejected when parsing the mapping, so this is always an object.
+        current = current
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .unwrap();
+    }
+    current.insert(last.clone(), value);
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn generate_json_schema(mapping: &CompiledMapping, definition: &ArduDefinition) -> Vec<u8> {
+    let mut properties = Map::new();
+
+    for (path, source) in &mapping.fields {
+        let leaf_schema = match source {
+            FieldSource::Timestamp => json!({
+                "type": "object",
+                "properties": { "sec": { "type": "integer" }, "nsec": { "type": "integer" } }
+            }),
+            FieldSource::Label { label, .. } => {
+                let is_string = definition
+                    .labels
+                    .iter()
+                    .position(|l| l == label)
+                    .and_then(|idx| definition.ardu_fmt.format_str.chars().nth(idx))
+                    .is_some_and(|c| matches!(c, 'n' | 'N' | 'Z'));
+                json!({ "type": if is_string { "string" } else { "number" } })
+            }
+            FieldSource::Constant(value) => json!({ "type": json_type(value) }),
+        };
+
+        // nested objects get their own { "type": "object", "properties": ... } wrapper
+        let (last, parents) = path.split_last().unwrap();
+        let mut current = &mut properties;
+        for key in parents {
+            let entry = current
+                .entry(key.clone())
+                .or_insert_with(|| json!({ "type": "object", "properties": {} }));
+            current = entry["properties"].as_object_mut().unwrap();
+        }
+        current.insert(last.clone(), leaf_schema);
+    }
+
+    let schema_json = json!({
+        "type": "object",
+        "title": mapping.schema_name,
+        "properties": properties
+    });
+
+    serde_json::to_vec(&schema_json).unwrap()
+}
+
+impl Transformer for MappingTransformer {
+    fn check_registered_to_transform(&mut self, definition: &ArduDefinition) -> bool {
+        let mut indices = Vec::new();
+
+        for (idx, mapping) in self.mappings.iter_mut().enumerate() {
+            if mapping.message != definition.ardu_fmt.name {
+                continue;
+            }
+
+            let missing: Vec<&str> = mapping
+                .fields
+                .iter()
+                .filter_map(|(_, source)| match source {
+                    FieldSource::Label { label, .. } if !definition.labels.contains(label) => {
+                        Some(label.as_str())
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            if !missing.is_empty() {
+                eprintln!(
+                    "WARNING: mapping for {} skipped, the message has no field(s): {}",
+                    mapping.topic,
+                    missing.join(", ")
+                );
+                continue;
+            }
+
+            mapping.schema_data = generate_json_schema(mapping, definition);
+            indices.push(idx);
+        }
+
+        if indices.is_empty() {
+            self.subscriptions.remove(&definition.ardu_fmt.type_id);
+            false
+        } else {
+            self.subscriptions
+                .insert(definition.ardu_fmt.type_id, indices);
+            true
+        }
+    }
+
+    fn transform(&mut self, msg: &ArduMessage) -> Result<Vec<TransformedMessage>> {
+        let mut output = Vec::new();
+
+        // this unwrap should never fail, unless there's a critical bug in the caller pipeline.
+        let indices = self.subscriptions.get(&msg.type_id).unwrap();
+
+        for &idx in indices {
+            let mapping = &self.mappings[idx];
+            let mut obj = Map::new();
+
+            for (path, source) in &mapping.fields {
+                let value = match source {
+                    FieldSource::Timestamp => json!({
+                        "sec": msg.current_ts / 1_000_000_000,
+                        "nsec": msg.current_ts % 1_000_000_000
+                    }),
+                    FieldSource::Label {
+                        label,
+                        scale,
+                        offset,
+                    } => match msg.get(label) {
+                        Some(LogValue::Str(s)) => json!(s),
+                        // unscaled integers stay integers, so that e.g. enums and bitmasks stay readable.
+                        Some(v) if *scale == 1.0 && *offset == 0.0 => v.into(),
+                        Some(v) => v
+                            .as_f64()
+                            .map(|v| v * scale + offset)
+                            .filter(|v| v.is_finite())
+                            .map_or(Value::Null, |v| json!(v)),
+                        None => Value::Null,
+                    },
+                    FieldSource::Constant(value) => value.clone(),
+                };
+
+                insert_at(&mut obj, path, value);
+            }
+
+            output.push(TransformedMessage {
+                topic: mapping.topic.clone(),
+                schema_name: mapping.schema_name.clone(),
+                schema_encoding: "jsonschema".to_string(),
+                schema_data: mapping.schema_data.clone(),
+                payload: serde_json::to_vec(&obj)?,
+            });
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{convert_to_mcap, read_mcap_messages, LogBuilder};
+    use approx::assert_relative_eq;
+
+    const MAPPING: &str = r#"{
+      "mappings": [
+        {
+          "message": "BARO",
+          "topic": "/custom/baro",
+          "schema": "custom.Baro",
+          "fields": {
+            "timestamp": "$timestamp",
+            "frame_id": { "value": "base_link" },
+            "altitude": "Alt",
+            "pressure.hpa": { "field": "Press", "scale": 0.01 },
+            "pressure.raw": "Press"
+          }
+        },
+        {
+          "message": "BARO",
+          "topic": "/custom/broken",
+          "schema": "custom.Broken",
+          "fields": { "x": "NoSuchField" }
+        }
+      ]
+    }"#;
+
+    #[test]
+    fn test_mapping() {
+        let mut log = LogBuilder::new();
+        log.definition(10, "BARO", "QfI", "TimeUS,Alt,Press")
+            .message(
+                10,
+                &[
+                    LogValue::UInt(1_500_000),
+                    LogValue::Float(12.5),
+                    LogValue::UInt(101325),
+                ],
+            );
+
+        let transformer = MappingTransformer::from_json(MAPPING).unwrap();
+        let mcap = convert_to_mcap(&log.build(), vec![Box::new(transformer)]).unwrap();
+        let messages = read_mcap_messages(&mcap).unwrap();
+
+        // the second mapping refers to a field BARO doesn't have, and is skipped.
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].topic, "/custom/baro");
+        assert_eq!(messages[0].schema_name, "custom.Baro");
+
+        let payload = &messages[0].payload;
+        assert_eq!(payload["timestamp"], json!({"sec": 1, "nsec": 500_000_000}));
+        assert_eq!(payload["frame_id"], "base_link");
+        assert_eq!(payload["altitude"], 12.5);
+        assert_relative_eq!(payload["pressure"]["hpa"].as_f64().unwrap(), 1013.25);
+        assert_eq!(payload["pressure"]["raw"], 101325);
+    }
+
+    #[test]
+    fn test_invalid_mappings() {
+        let mapping = |fields: &str| {
+            MappingTransformer::from_json(&format!(
+                r#"{{ "mappings": [{{ "message": "M", "topic": "/t", "schema": "s", "fields": {} }}] }}"#,
+                fields
+            ))
+        };
+
+        assert!(mapping(r#"{ "x": "A" }"#).is_ok());
+        assert!(mapping(r#"{ "x": 42 }"#).is_err());
+        assert!(mapping(r#"{ "x": { "field": "A", "scael": 2 } }"#).is_err());
+        assert!(mapping(r#"{ "x": "A", "x.y": "B" }"#).is_err());
+        assert!(mapping(r#"{ "x..y": "A" }"#).is_err());
+    }
+}
@@ -0,0 +1,450 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Cursor, Read},
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Context, Result};
+use binrw::BinRead;
+
+use crate::{
+    reader::{
+        ArduDefinition, ArduFrame, ArduReader, FmtPacket, FMT_MSG_ID, HEADER_LEN, HEADER_MAGIC,
+    },
+    segments::SegmentedFile,
+};
+
+// Small, so that the first frames are handed over while the rest of the log is still being read and decoded.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+// Decoded frames take about 10 times the space of their raw bytes. Chunks are only read ahead as long as
+// the decoded data waiting to be handed over stays below the budget. This also caps the number of decoding threads,
+// more couldn't have a chunk to work on.
+const DECODED_SIZE_FACTOR: usize = 10;
+const DECODED_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+const MAX_CHUNKS_IN_FLIGHT: usize = DECODED_MEMORY_BUDGET / (CHUNK_SIZE * DECODED_SIZE_FACTOR);
+
+// type id, length, name, format, labels
+const FMT_BODY_LEN: usize = 1 + 1 + 4 + 16 + 64;
+
+struct Chunk {
+    // position of the chunk in the log, for error messages.
+    start: u64,
+    data: Vec<u8>,
+    // the definitions known at `start`, so that the chunk can be decoded on its own.
+    definitions: HashMap<u8, ArduDefinition>,
+}
+
+// Reads the log, cutting it into chunks at message boundaries. Only FMT packets are parsed, other messages are
+// just measured. This mirrors what ArduReader accepts: as soon as something doesn't look like a valid packet,
+// we stop cutting, and let the last chunk's reader deal with it, exactly like a sequential read would.
+struct ChunkSplitter<R: Read> {
+    source: R,
+    chunk_size: usize,
+    definitions: HashMap<u8, ArduDefinition>,
+    position: u64,
+    done: bool,
+}
+
+impl<R: Read> ChunkSplitter<R> {
+    fn new(source: R, chunk_size: usize) -> Self {
+        Self {
+            source,
+            chunk_size,
+            definitions: HashMap::new(),
+            position: 0,
+            done: false,
+        }
+    }
+
+    // appends the next `len` bytes to `data`, `false` if the log ended before that.
+    fn read_into(&mut self, data: &mut Vec<u8>, len: usize) -> Result<bool> {
+        let n = (&mut self.source).take(len as u64).read_to_end(data)?;
+        Ok(n == len)
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let start = self.position;
+        let definitions = self.definitions.clone();
+        let mut data = Vec::with_capacity(self.chunk_size);
+
+        while data.len() < self.chunk_size {
+            let packet_start = data.len();
+            if !self.read_into(&mut data, HEADER_LEN as usize)?
+                || data[packet_start..packet_start + 2] != HEADER_MAGIC
+            {
+                self.done = true;
+                break;
+            }
+
+            let msg_id = data[packet_start + 2];
+            let body_len = if msg_id == FMT_MSG_ID {
+                FMT_BODY_LEN
+            } else {
+                match self
+                    .definitions
+                    .get(&msg_id)
+                    .and_then(|d| d.message_length())
+                {
+                    Some(len) => (len - HEADER_LEN) as usize,
+                    None => {
+                        self.done = true;
+                        break;
+                    }
+                }
+            };
+
+            if !self.read_into(&mut data, body_len)? {
+                self.done = true;
+                break;
+            }
+
+            if msg_id == FMT_MSG_ID {
+                let body = &data[packet_start + HEADER_LEN as usize..];
+                let Ok(ardu_fmt) = FmtPacket::read(&mut Cursor::new(body)) else {
+                    self.done = true;
+                    break;
+                };
+                let definition = ArduDefinition::from_fmt(ardu_fmt);
+                self.definitions
+                    .insert(definition.ardu_fmt.type_id, definition);
+            }
+        }
+
+        self.position += data.len() as u64;
+        Ok(Some(Chunk {
+            start,
+            data,
+            definitions,
+        }))
+    }
+}
+
+// The frames decoded before an error are kept, so that they are handed over before the error, like a sequential read would.
+fn decode_chunk(chunk: Chunk) -> (Vec<ArduFrame>, Result<()>) {
+    let mut reader =
+        ArduReader::with_state(Cursor::new(chunk.data), chunk.definitions, 0, chunk.start);
+    let mut frames = Vec::new();
+
+    loop {
+        match reader.read() {
+            Ok(ArduFrame::Eof) => return (frames, Ok(())),
+            Ok(frame) => frames.push(frame),
+            Err(e) => return (frames, Err(e)),
+        }
+    }
+}
+
+type DecodedChunk = (usize, (Vec<ArduFrame>, Result<()>));
+
+// Decoded chunks arrive in whatever order the threads finish them, and are handed over in log order.
+// Returns the number of chunks handed over.
+fn hand_over_in_order(
+    decoded_rx: Receiver<DecodedChunk>,
+    permit_rx: Receiver<()>,
+    on_frame: &mut impl FnMut(ArduFrame) -> Result<()>,
+) -> Result<usize> {
+    let mut pending = HashMap::new();
+    let mut next_index = 0;
+
+    // each chunk was decoded not knowing the timestamp its predecessor ended with,
+    // so its leading messages without TimeUS are patched up here, in order.
+    let mut last_timestamp = 0;
+
+    for (index, decoded) in decoded_rx {
+        pending.insert(index, decoded);
+
+        while let Some((frames, status)) = pending.remove(&next_index) {
+            for mut frame in frames {
+                if let ArduFrame::ArduMessage(message) = &mut frame {
+                    if message.current_ts > 0 {
+                        last_timestamp = message.current_ts;
+                    } else {
+                        message.current_ts = last_timestamp;
+                    }
+                }
+                on_frame(frame)?;
+            }
+            status?;
+
+            // the chunk is gone, the splitter may read another one.
+            let _ = permit_rx.recv();
+            next_index += 1;
+        }
+    }
+
+    Ok(next_index)
+}
+
+// Takes jobs off the shared queue until it's closed, sending back their results, tagged with the job's index.
+// The queue is only locked while receiving, so that the workers run concurrently.
+fn run_worker<J, R>(
+    jobs_rx: &Mutex<Receiver<(usize, J)>>,
+    results_tx: Sender<(usize, R)>,
+    work: impl Fn(J) -> R,
+) {
+    loop {
+        let next = jobs_rx.lock().unwrap().recv();
+        let Ok((index, job)) = next else {
+            break;
+        };
+        if results_tx.send((index, work(job))).is_err() {
+            break;
+        }
+    }
+}
+
+// One thread reads and splits the log, `jobs` threads decode the chunks, and the calling thread hands the frames over.
+// Dropping the receiving ends, when `on_frame` fails, stops all the others.
+fn decode_parallel(
+    source: impl Read + Send,
+    jobs: usize,
+    chunk_size: usize,
+    max_chunks_in_flight: usize,
+    mut on_frame: impl FnMut(ArduFrame) -> Result<()>,
+) -> Result<()> {
+    let (chunk_tx, chunk_rx) = mpsc::channel::<(usize, Chunk)>();
+    let chunk_rx = Mutex::new(chunk_rx);
+    let (decoded_tx, decoded_rx) = mpsc::channel();
+    // one permit per chunk that was read, but not handed over yet.
+    let (permit_tx, permit_rx) = mpsc::sync_channel(max_chunks_in_flight.max(1));
+
+    thread::scope(|s| {
+        let splitter = s.spawn(move || -> Result<usize> {
+            let mut splitter = ChunkSplitter::new(source, chunk_size.max(1));
+            let mut index = 0;
+
+            while permit_tx.send(()).is_ok() {
+                let Some(chunk) = splitter.next_chunk()? else {
+                    break;
+                };
+                if chunk_tx.send((index, chunk)).is_err() {
+                    break;
+                }
+                index += 1;
+            }
+
+            Ok(index)
+        });
+
+        for _ in 0..jobs.clamp(1, max_chunks_in_flight.max(1)) {
+            let chunk_rx = &chunk_rx;
+            let decoded_tx = decoded_tx.clone();
+            s.spawn(move || run_worker(chunk_rx, decoded_tx, decode_chunk));
+        }
+        drop(decoded_tx);
+
+        let handed_over = hand_over_in_order(decoded_rx, permit_rx, &mut on_frame)?;
+        let read = splitter.join().expect("log splitter thread panicked")?;
+        if handed_over != read {
+            return Err(anyhow!("a decoder thread panicked"));
+        }
+
+        on_frame(ArduFrame::Eof)
+    })
+}
+
+/// One job per available core.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// The number of threads actually decoding for `jobs`: at most one per chunk in flight, which the memory budget limits.
+pub fn decoding_jobs(jobs: usize) -> usize {
+    jobs.clamp(1, MAX_CHUNKS_IN_FLIGHT)
+}
+
+/// Decodes a log on up to `decoding_jobs(jobs)` threads while reading it, handing the frames to `on_frame` in log order,
+/// i.e. exactly the sequence `ArduReader::read` would have produced, terminated by `ArduFrame::Eof`.
+/// `on_frame` runs on the calling thread, while later parts of the log are being decoded.
+/// Only a bounded part of the log is held in memory at any time.
+pub fn read_parallel(
+    source: impl Read + Send,
+    jobs: usize,
+    on_frame: impl FnMut(ArduFrame) -> Result<()>,
+) -> Result<()> {
+    decode_parallel(
+        BufReader::new(source),
+        jobs,
+        CHUNK_SIZE,
+        MAX_CHUNKS_IN_FLIGHT,
+        on_frame,
+    )
+}
+
+/// Same as `read_parallel`, for an in-memory log.
+pub fn read_frames_parallel(
+    data: &[u8],
+    jobs: usize,
+    on_frame: impl FnMut(ArduFrame) -> Result<()>,
+) -> Result<()> {
+    decode_parallel(data, jobs, CHUNK_SIZE, MAX_CHUNKS_IN_FLIGHT, on_frame)
+}
+
+/// Same as `read_parallel`, for a log file.
+pub fn read_file_parallel(
+    filename: &str,
+    jobs: usize,
+    on_frame: impl FnMut(ArduFrame) -> Result<()>,
+) -> Result<()> {
+    let file = File::open(filename).with_context(|| format!("Failed opening file {}", filename))?;
+    read_parallel(file, jobs, on_frame)
+}
+
+/// Same as `read_file_parallel`, for a log split across several files, read back to back.
+pub fn read_segments_parallel(
+    filenames: &[impl AsRef<Path>],
+    jobs: usize,
+    on_frame: impl FnMut(ArduFrame) -> Result<()>,
+) -> Result<()> {
+    read_parallel(SegmentedFile::open(filenames)?, jobs, on_frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{reader::LogValue, testing::LogBuilder};
+
+    fn test_log() -> Vec<u8> {
+        let mut log = LogBuilder::new();
+        log.definition(10, "TST", "Qi", "TimeUS,Value");
+
+        for i in 0..50u64 {
+            log.message(
+                10,
+                &[LogValue::UInt(1000 + i * 100), LogValue::Int(i as i64 * -3)],
+            );
+
+            if i == 20 {
+                // a late definition, for a message type without a timestamp
+                log.definition(11, "NTS", "B", "Flag");
+            }
+            if i > 20 {
+                log.message(11, &[LogValue::UInt(i)]);
+            }
+        }
+
+        // a truncated trailing message, as left behind by a power loss.
+        log.raw(&[0xA3, 0x95, 10, 1, 2, 3]);
+        log.build()
+    }
+
+    fn summarize(frame: &ArduFrame) -> String {
+        match frame {
+            ArduFrame::ArduDefinition(d) => {
+                format!("def {} {}", d.ardu_fmt.type_id, d.ardu_fmt.name)
+            }
+            ArduFrame::ArduMessage(m) => {
+                format!("msg {} {} {:?}", m.type_id, m.current_ts, m.values)
+            }
+            ArduFrame::Eof => "eof".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let data = test_log();
+
+        let mut expected = Vec::new();
+        let mut reader = ArduReader::from_reader(Cursor::new(&data));
+        loop {
+            let frame = reader.read().unwrap();
+            expected.push(summarize(&frame));
+            if let ArduFrame::Eof = frame {
+                break;
+            }
+        }
+        assert_eq!(expected.len(), 2 + 50 + 1 + 29 + 1);
+
+        for (jobs, chunk_size, in_flight) in [
+            (1, 1, 1),
+            (2, 1, 1),
+            (2, 1, 8),
+            (3, 50, 2),
+            (4, 200, 4),
+            (8, data.len(), 8),
+        ] {
+            let mut actual = Vec::new();
+            decode_parallel(&data[..], jobs, chunk_size, in_flight, |frame| {
+                actual.push(summarize(&frame));
+                Ok(())
+            })
+            .unwrap();
+
+            assert_eq!(
+                actual, expected,
+                "jobs: {}, chunk_size: {}, in flight: {}",
+                jobs, chunk_size, in_flight
+            );
+        }
+    }
+
+    #[test]
+    fn test_workers_run_concurrently() {
+        let (jobs_tx, jobs_rx) = mpsc::channel();
+        let jobs_rx = Mutex::new(jobs_rx);
+        let (results_tx, results_rx) = mpsc::channel();
+        for index in 0..8 {
+            jobs_tx.send((index, index)).unwrap();
+        }
+        drop(jobs_tx);
+
+        let start = std::time::Instant::now();
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let (jobs_rx, results_tx) = (&jobs_rx, results_tx.clone());
+                s.spawn(move || {
+                    run_worker(jobs_rx, results_tx, |job: usize| {
+                        thread::sleep(std::time::Duration::from_millis(100));
+                        job * 2
+                    })
+                });
+            }
+        });
+        drop(results_tx);
+
+        // 8 jobs of 100ms on 4 workers: 200ms if they overlap, 800ms if they take turns.
+        assert!(start.elapsed().as_millis() < 600, "{:?}", start.elapsed());
+        let mut results: Vec<_> = results_rx.iter().collect();
+        results.sort();
+        assert_eq!(results, (0..8).map(|i| (i, i * 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_parallel_errors_like_sequential() {
+        let mut data = test_log();
+        data.truncate(data.len() - 6);
+        // a message type that was never defined
+        data.extend([0xA3, 0x95, 42, 0, 0, 0]);
+
+        let mut reader = ArduReader::from_reader(Cursor::new(&data));
+        let mut expected_frames = 0;
+        let expected = loop {
+            match reader.read() {
+                Ok(ArduFrame::Eof) => panic!("the log should fail to decode"),
+                Ok(_) => expected_frames += 1,
+                Err(e) => break e.to_string(),
+            }
+        };
+
+        let mut frames = 0;
+        let error = decode_parallel(&data[..], 4, 100, 2, |_| {
+            frames += 1;
+            Ok(())
+        })
+        .unwrap_err();
+
+        assert_eq!(error.to_string(), expected);
+        assert_eq!(frames, expected_frames);
+    }
+}
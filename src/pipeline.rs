@@ -1,16 +1,22 @@
 use std::{
     collections::{BTreeMap, HashMap},
     fs::File,
+    io::{Seek, Write},
     path::{Path, PathBuf},
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 
 use mcap::{records::MessageHeader, Writer};
 
 use crate::{
+    parallel::read_segments_parallel,
     reader::{ArduFrame, ArduReader},
-    transformers::{FoxgloveFusedTransformer, GenericTransformer, Transformer},
+    segments::{is_continuation_segment, SegmentedFile},
+    transformers::{
+        CrashEventTransformer, FoxgloveFusedTransformer, GenericTransformer,
+        SimComparisonTransformer, TerrainAltitudeTransformer, TransformedMessage, Transformer,
+    },
 };
 
 fn with_mcap_extension(name: &str) -> PathBuf {
@@ -24,85 +30,225 @@ struct McapChannelInfo {
     sequence: u32,
 }
 
-pub fn process_ardupilot_file(filename: &str) -> Result<()> {
-    let mut reader = ArduReader::new(filename);
-    let mcap_filename = with_mcap_extension(filename);
-
-    let mcap_file = File::create(mcap_filename)?;
-    let mut mcap_writer = Writer::new(mcap_file)?;
-
-    let mut channel_map = HashMap::<(String, String), McapChannelInfo>::new();
-
-    let mut transformers: Vec<Box<dyn Transformer>> = vec![
+pub fn default_transformers() -> Vec<Box<dyn Transformer>> {
+    vec![
         Box::new(GenericTransformer::new()),
         Box::new(FoxgloveFusedTransformer::new()),
-    ];
+        Box::new(CrashEventTransformer::new()),
+        Box::new(TerrainAltitudeTransformer::new()),
+        Box::new(SimComparisonTransformer::new()),
+    ]
+}
 
-    let mut subscriptions = HashMap::<u8, Vec<usize>>::new();
+/// Routes `ArduFrame`s to the transformers that registered for them.
+pub struct TransformerSet {
+    transformers: Vec<Box<dyn Transformer>>,
+    subscriptions: HashMap<u8, Vec<usize>>,
+}
 
-    loop {
-        match reader.read()? {
-            ArduFrame::Eof => {
-                mcap_writer.finish()?;
-                return Ok(());
-            }
+impl TransformerSet {
+    pub fn new(transformers: Vec<Box<dyn Transformer>>) -> Self {
+        Self {
+            transformers,
+            subscriptions: HashMap::new(),
+        }
+    }
+
+    pub fn transform_frame(&mut self, frame: &ArduFrame) -> Result<Vec<TransformedMessage>> {
+        let mut output = Vec::new();
+
+        match frame {
+            ArduFrame::Eof => {}
             ArduFrame::ArduDefinition(definition) => {
                 let mut active_indices = Vec::new();
-                for (i, t) in transformers.iter_mut().enumerate() {
-                    if t.check_registered_to_transform(&definition) {
+                for (i, t) in self.transformers.iter_mut().enumerate() {
+                    if t.check_registered_to_transform(definition) {
                         active_indices.push(i);
                     }
                 }
 
-                subscriptions.insert(definition.ardu_fmt.type_id, active_indices);
+                self.subscriptions
+                    .insert(definition.ardu_fmt.type_id, active_indices);
             }
             ArduFrame::ArduMessage(message) => {
-                if let Some(indices) = subscriptions.get(&message.type_id) {
+                if let Some(indices) = self.subscriptions.get(&message.type_id) {
                     for &i in indices {
-                        let out_msgs = transformers[i].transform(&message)?;
-
-                        for out_msg in out_msgs {
-                            let key = (out_msg.topic.clone(), out_msg.schema_name.clone());
-
-                            if !channel_map.contains_key(&key) {
-                                let schema_id = mcap_writer.add_schema(
-                                    &out_msg.schema_name,
-                                    &out_msg.schema_encoding,
-                                    &out_msg.schema_data,
-                                )?;
-
-                                let channel_id = mcap_writer.add_channel(
-                                    schema_id,
-                                    &out_msg.topic,
-                                    "json",
-                                    &BTreeMap::new(),
-                                )?;
-
-                                channel_map.insert(
-                                    key.clone(),
-                                    McapChannelInfo {
-                                        channel_id,
-                                        sequence: 0,
-                                    },
-                                );
-                            }
-
-                            let channel_info = channel_map.get_mut(&key).unwrap();
-                            mcap_writer.write_to_known_channel(
-                                &MessageHeader {
-                                    channel_id: channel_info.channel_id,
-                                    sequence: channel_info.sequence,
-                                    log_time: message.current_ts,
-                                    publish_time: message.current_ts,
-                                },
-                                &out_msg.payload,
-                            )?;
-
-                            channel_info.sequence += 1;
-                        }
+                        output.extend(self.transformers[i].transform(message)?);
                     }
                 }
             }
         }
+
+        Ok(output)
+    }
+}
+
+/// Writes `TransformedMessage`s to MCAP, creating a channel for every new topic and schema pair.
+pub struct McapSink<W: Write + Seek> {
+    mcap_writer: Writer<W>,
+    channel_map: HashMap<(String, String), McapChannelInfo>,
+}
+
+impl<W: Write + Seek> McapSink<W> {
+    pub fn new(writer: W) -> Result<Self> {
+        Ok(Self {
+            mcap_writer: Writer::new(writer)?,
+            channel_map: HashMap::new(),
+        })
+    }
+
+    pub fn write(&mut self, out_msg: &TransformedMessage, log_time: u64) -> Result<()> {
+        let key = (out_msg.topic.clone(), out_msg.schema_name.clone());
+
+        if !self.channel_map.contains_key(&key) {
+            let schema_id = self.mcap_writer.add_schema(
+                &out_msg.schema_name,
+                &out_msg.schema_encoding,
+                &out_msg.schema_data,
+            )?;
+
+            let channel_id = self.mcap_writer.add_channel(
+                schema_id,
+                &out_msg.topic,
+                "json",
+                &BTreeMap::new(),
+            )?;
+
+            self.channel_map.insert(
+                key.clone(),
+                McapChannelInfo {
+                    channel_id,
+                    sequence: 0,
+                },
+            );
+        }
+
+        let channel_info = self.channel_map.get_mut(&key).unwrap();
+        self.mcap_writer.write_to_known_channel(
+            &MessageHeader {
+                channel_id: channel_info.channel_id,
+                sequence: channel_info.sequence,
+                log_time,
+                publish_time: log_time,
+            },
+            &out_msg.payload,
+        )?;
+
+        channel_info.sequence += 1;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        self.mcap_writer.finish()?;
+        Ok(())
+    }
+
+    /// The underlying writer. Only a complete MCAP once `finish` has been called.
+    pub fn into_inner(self) -> W {
+        self.mcap_writer.into_inner()
+    }
+}
+
+/// Routes `ArduFrame`s through the transformers, and writes whatever they emit to MCAP channels.
+pub struct McapPipeline<W: Write + Seek> {
+    transformers: TransformerSet,
+    sink: McapSink<W>,
+}
+
+impl<W: Write + Seek> McapPipeline<W> {
+    pub fn new(writer: W, transformers: Vec<Box<dyn Transformer>>) -> Result<Self> {
+        Ok(Self {
+            transformers: TransformerSet::new(transformers),
+            sink: McapSink::new(writer)?,
+        })
+    }
+
+    /// Returns `true` once `ArduFrame::Eof` has been processed, and the MCAP is finished.
+    pub fn process_frame(&mut self, frame: ArduFrame) -> Result<bool> {
+        if let ArduFrame::Eof = frame {
+            self.sink.finish()?;
+            return Ok(true);
+        }
+
+        let log_time = match &frame {
+            ArduFrame::ArduMessage(message) => message.current_ts,
+            _ => 0,
+        };
+
+        for out_msg in self.transformers.transform_frame(&frame)? {
+            self.sink.write(&out_msg, log_time)?;
+        }
+
+        Ok(false)
+    }
+
+    /// The underlying writer. Only a complete MCAP once `ArduFrame::Eof` has been processed.
+    pub fn into_inner(self) -> W {
+        self.sink.into_inner()
+    }
+}
+
+pub fn process_ardupilot_file(filename: &str) -> Result<()> {
+    process_ardupilot_file_with(filename, 1, default_transformers())
+}
+
+/// Same output as `process_ardupilot_file`, but decodes the log on up to `jobs` threads,
+/// while the calling thread transforms and writes.
+pub fn process_ardupilot_file_parallel(filename: &str, jobs: usize) -> Result<()> {
+    process_ardupilot_file_with(filename, jobs, default_transformers())
+}
+
+/// Converts with a custom set of transformers, e.g. `default_transformers()` plus a `MappingTransformer`.
+/// With `jobs` > 1 the log is decoded in parallel. Either way, it is streamed rather than read into memory.
+pub fn process_ardupilot_file_with(
+    filename: &str,
+    jobs: usize,
+    transformers: Vec<Box<dyn Transformer>>,
+) -> Result<()> {
+    process_ardupilot_segments_with(&[filename], jobs, transformers)
+}
+
+/// Every log starts with FMT packets. A file that doesn't is likely a continuation segment,
+/// which can't be decoded without the segments before it.
+pub fn check_log_start(filename: &str) -> Result<()> {
+    if is_continuation_segment(filename)
+        .with_context(|| format!("Failed opening file {}", filename))?
+    {
+        return Err(anyhow!(
+            "{} doesn't start with FMT packets: it continues another log, and can only be converted after the segments before it",
+            filename
+        ));
+    }
+    Ok(())
+}
+
+/// Same as `process_ardupilot_file_with`, for a log split across several files.
+/// The segments are read back to back, in the given order, into a single MCAP named after the first one.
+pub fn process_ardupilot_segments_with(
+    filenames: &[&str],
+    jobs: usize,
+    transformers: Vec<Box<dyn Transformer>>,
+) -> Result<()> {
+    let first = filenames
+        .first()
+        .ok_or_else(|| anyhow!("No log files to convert"))?;
+    check_log_start(first)?;
+
+    let mcap_file = File::create(with_mcap_extension(first))?;
+    let mut pipeline = McapPipeline::new(mcap_file, transformers)?;
+
+    if jobs > 1 {
+        read_segments_parallel(filenames, jobs, |frame| {
+            pipeline.process_frame(frame)?;
+            Ok(())
+        })
+    } else if let [filename] = filenames {
+        let mut reader = ArduReader::new(filename);
+        while !pipeline.process_frame(reader.read()?)? {}
+        Ok(())
+    } else {
+        let mut reader = ArduReader::from_reader(SegmentedFile::open(filenames)?);
+        while !pipeline.process_frame(reader.read()?)? {}
+        Ok(())
     }
 }
@@ -1,9 +1,18 @@
-use std::{collections::HashMap, fs::File, io::Seek};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    sync::Arc,
+};
 
 use anyhow::{anyhow, Context, Result};
 use binrw::{binread, BinRead};
 use serde_json::{json, Map, Value};
 
+pub(crate) const HEADER_MAGIC: [u8; 2] = [0xA3, 0x95];
+pub(crate) const HEADER_LEN: u64 = 3;
+pub(crate) const FMT_MSG_ID: u8 = 128;
+
 #[binread]
 #[br(little, magic = b"\xA3\x95")]
 struct PacketHeader {
@@ -15,11 +24,12 @@ struct PacketHeader {
 #[derive(Debug, Clone)]
 pub struct FmtPacket {
     pub type_id: u8,
+    #[allow(dead_code)] // message lengths are derived from format_str instead
     length: u8,
     #[br(map = |bytes: [u8; 4]| sanitize_str(&bytes))]
     pub name: String,
     #[br(map = |bytes: [u8; 16]| sanitize_str(&bytes))]
-    format_str: String,
+    pub format_str: String,
     #[br(map = |bytes: [u8; 64]| sanitize_str(&bytes))]
     labels: String,
 }
@@ -30,8 +40,8 @@ fn sanitize_str(bytes: &[u8]) -> String {
         .to_string()
 }
 
-#[derive(Debug)]
-enum LogValue {
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogValue {
     Int(i64),
     UInt(u64),
     Float(f32),
@@ -51,6 +61,54 @@ impl std::fmt::Display for LogValue {
     }
 }
 
+impl LogValue {
+    /// Any numeric value, widened to f64. Unlike the JSON representation, non-finite floats are kept as-is.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            LogValue::Int(v) => Some(*v as f64),
+            LogValue::UInt(v) => Some(*v as f64),
+            LogValue::Float(v) => Some(*v as f64),
+            LogValue::Double(v) => Some(*v),
+            LogValue::Str(_) => None,
+        }
+    }
+
+    /// Integer values only, `None` if the value doesn't fit.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            LogValue::Int(v) => Some(*v),
+            LogValue::UInt(v) => i64::try_from(*v).ok(),
+            _ => None,
+        }
+    }
+
+    /// Integer values only, `None` if the value is negative.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            LogValue::Int(v) => u64::try_from(*v).ok(),
+            LogValue::UInt(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            LogValue::Str(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, LogValue::Float(_) | LogValue::Double(_))
+    }
+}
+
+impl From<&LogValue> for Value {
+    fn from(value: &LogValue) -> Self {
+        value.clone().into()
+    }
+}
+
 impl From<LogValue> for Value {
     fn from(value: LogValue) -> Self {
         use LogValue::*;
@@ -77,7 +135,7 @@ impl From<LogValue> for Value {
 }
 
 // we use u64 to be compatible with seek() and current_position() math.
-fn field_length(fmt_char: char) -> Result<u64> {
+pub(crate) fn field_length(fmt_char: char) -> Result<u64> {
     match fmt_char {
         'b' | 'B' | 'M' => Ok(1),
         'h' | 'c' | 'H' | 'C' => Ok(2),
@@ -133,11 +191,16 @@ fn parse_value(
     }
 }
 
-pub struct ArduReader {
+pub(crate) trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+pub struct ArduReader<'a> {
     filename: String,
-    file: Option<File>,
+    file: Option<Box<dyn ReadSeek + Send + 'a>>,
     definitions: HashMap<u8, ArduDefinition>,
     last_timestamp: u64,
+    // position of the source's first byte in the log, for error messages.
+    start: u64,
 }
 
 pub enum ArduFrame {
@@ -149,28 +212,129 @@ pub enum ArduFrame {
 #[derive(Clone)]
 pub struct ArduDefinition {
     pub ardu_fmt: FmtPacket,
-    pub labels: Vec<String>,
+    /// shared with every message of this type.
+    pub labels: Arc<[String]>,
+}
+
+impl ArduDefinition {
+    pub(crate) fn from_fmt(ardu_fmt: FmtPacket) -> Self {
+        let labels: Arc<[String]> = ardu_fmt
+            .labels
+            .split(",")
+            .map(|s| s.trim().to_string())
+            .collect();
+
+        Self { ardu_fmt, labels }
+    }
+
+    /// Length of a message of this type on disk, header included. `None` if the format string can't be decoded.
+    pub fn message_length(&self) -> Option<u64> {
+        self.ardu_fmt
+            .format_str
+            .chars()
+            .map(|c| field_length(c).ok())
+            .sum::<Option<u64>>()
+            .map(|len| len + HEADER_LEN)
+    }
 }
 
 pub struct ArduMessage {
     pub type_id: u8,
     pub current_ts: u64,
-    pub json_obj: Map<String, Value>,
+    /// the definition's labels.
+    pub labels: Arc<[String]>,
+    /// decoded values, in the order of `labels`.
+    pub values: Vec<LogValue>,
 }
 
-impl ArduReader {
+impl ArduMessage {
+    pub fn new(type_id: u8, current_ts: u64, labels: Arc<[String]>, values: Vec<LogValue>) -> Self {
+        Self {
+            type_id,
+            current_ts,
+            labels,
+            values,
+        }
+    }
+
+    /// (label, value) pairs, in log order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &LogValue)> {
+        self.labels.iter().map(String::as_str).zip(&self.values)
+    }
+
+    /// The message as a JSON object, with non-finite floats as `null`. Built on every call.
+    pub fn to_json(&self) -> Map<String, Value> {
+        self.fields()
+            .map(|(label, val)| (label.to_string(), val.into()))
+            .collect()
+    }
+
+    pub fn get(&self, label: &str) -> Option<&LogValue> {
+        self.fields().find(|(l, _)| *l == label).map(|(_, val)| val)
+    }
+
+    pub fn get_f64(&self, label: &str) -> Option<f64> {
+        self.get(label).and_then(LogValue::as_f64)
+    }
+
+    pub fn get_i64(&self, label: &str) -> Option<i64> {
+        self.get(label).and_then(LogValue::as_i64)
+    }
+
+    pub fn get_u64(&self, label: &str) -> Option<u64> {
+        self.get(label).and_then(LogValue::as_u64)
+    }
+
+    pub fn get_str(&self, label: &str) -> Option<&str> {
+        self.get(label).and_then(LogValue::as_str)
+    }
+}
+
+fn stream_len(stream: &mut (impl Seek + ?Sized)) -> Result<u64> {
+    let pos = stream.stream_position()?;
+    let len = stream.seek(SeekFrom::End(0))?;
+    stream.seek(SeekFrom::Start(pos))?;
+    Ok(len)
+}
+
+impl<'a> ArduReader<'a> {
     pub fn new(filename: &str) -> Self {
         Self {
             filename: filename.to_string(),
             file: None,
             definitions: HashMap::new(),
             last_timestamp: 0,
+            start: 0,
+        }
+    }
+
+    /// Reads from an already opened source, e.g. an in-memory `std::io::Cursor`.
+    pub fn from_reader(source: impl Read + Seek + Send + 'a) -> Self {
+        Self::with_state(source, HashMap::new(), 0, 0)
+    }
+
+    /// Resumes reading somewhere in the middle of a log, with the definitions and timestamp known at that point.
+    /// `start` is where `source` begins in the log.
+    pub(crate) fn with_state(
+        source: impl Read + Seek + Send + 'a,
+        definitions: HashMap<u8, ArduDefinition>,
+        last_timestamp: u64,
+        start: u64,
+    ) -> Self {
+        Self {
+            filename: String::new(),
+            file: Some(Box::new(source)),
+            definitions,
+            last_timestamp,
+            start,
         }
     }
 
     pub fn read(&mut self) -> Result<ArduFrame> {
         if self.file.is_none() {
-            self.file = Some(File::open(&self.filename).context("Failed opening file")?);
+            self.file = Some(Box::new(
+                File::open(&self.filename).context("Failed opening file")?,
+            ));
         }
 
         // we are now guaranteed unwrap will succeed.
@@ -187,41 +351,28 @@ impl ArduReader {
             }
         };
 
-        if header.msg_id == 128 {
-            let ardu_fmt = FmtPacket::read(file)?;
-
-            let labels: Vec<String> = ardu_fmt
-                .labels
-                .split(",")
-                .map(|s| s.trim().to_string())
-                .collect();
-
-            let definition = ArduDefinition {
-                ardu_fmt: ardu_fmt.clone(),
-                labels,
-            };
+        if header.msg_id == FMT_MSG_ID {
+            let definition = ArduDefinition::from_fmt(FmtPacket::read(file)?);
 
             self.definitions
-                .insert(ardu_fmt.type_id, definition.clone());
+                .insert(definition.ardu_fmt.type_id, definition.clone());
 
             return Ok(ArduFrame::ArduDefinition(definition));
         } else if let Some(definition) = self.definitions.get(&header.msg_id) {
             let mut current_ts = 0;
-            let mut json_obj = Map::new();
+            let mut values = Vec::with_capacity(definition.labels.len());
 
             for (idx, c) in definition.ardu_fmt.format_str.chars().enumerate() {
                 let val = parse_value(file, c);
 
-                let label = definition.labels[idx].clone();
-
                 let val = match val {
                     Ok(v) => v,
                     Err(e) => {
                         // if any of these fail, just let it fail with a "crpytic" error. Re-decorating the original error is too much trouble.
                         // Unless there's a cool syntax that allows it without too much boilerplate?
 
-                        let file_size = file.metadata()?.len();
-                        let current_pos = file.stream_position()?;
+                        let file_size = self.start + stream_len(file)?;
+                        let current_pos = self.start + file.stream_position()?;
                         let field_len = field_length(c)?;
 
                         if current_pos + field_len > file_size {
@@ -238,7 +389,7 @@ impl ArduReader {
                     }
                 };
 
-                if label == "TimeUS" {
+                if definition.labels.get(idx).is_some_and(|l| l == "TimeUS") {
                     if let LogValue::UInt(v) = val {
                         current_ts = v * 1000;
                     }
@@ -247,7 +398,7 @@ impl ArduReader {
                     }
                 }
 
-                json_obj.insert(label.clone(), val.into());
+                values.push(val);
             }
 
             if current_ts > 0 {
@@ -256,11 +407,8 @@ impl ArduReader {
                 current_ts = self.last_timestamp;
             }
 
-            let message = ArduMessage {
-                type_id: header.msg_id,
-                current_ts,
-                json_obj,
-            };
+            let message =
+                ArduMessage::new(header.msg_id, current_ts, definition.labels.clone(), values);
 
             return Ok(ArduFrame::ArduMessage(message));
         }
@@ -268,7 +416,45 @@ impl ArduReader {
         Err(anyhow!(
             "Error: Unknown msg ID {} at position {}.",
             header.msg_id,
-            file.stream_position()?
+            self.start + file.stream_position()?
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_accessors() {
+        let labels = ["Lat", "Mask", "Alt", "Bad", "TN"].map(String::from);
+        let msg = ArduMessage::new(
+            42,
+            0,
+            Arc::new(labels),
+            vec![
+                LogValue::Int(-473977420),
+                LogValue::UInt(u64::MAX),
+                LogValue::Double(488.123456789012),
+                LogValue::Float(f32::NAN),
+                LogValue::Str("IDLE".to_string()),
+            ],
+        );
+
+        assert_eq!(msg.get_i64("Lat"), Some(-473977420));
+        assert_eq!(msg.get_u64("Lat"), None);
+        assert_eq!(msg.get_u64("Mask"), Some(u64::MAX));
+        assert_eq!(msg.get_i64("Mask"), None);
+        assert_eq!(msg.get_f64("Alt"), Some(488.123456789012));
+        assert!(msg.get_f64("Bad").unwrap().is_nan());
+        assert_eq!(msg.get_str("TN"), Some("IDLE"));
+        assert_eq!(msg.get_f64("TN"), None);
+        assert_eq!(msg.get("Missing"), None);
+
+        // the JSON representation, with non-finite values nulled out.
+        let json_obj = msg.to_json();
+        assert_eq!(json_obj["Lat"], json!(-473977420));
+        assert_eq!(json_obj["Bad"], Value::Null);
+        assert_eq!(json_obj["TN"], json!("IDLE"));
+    }
+}
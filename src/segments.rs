@@ -0,0 +1,225 @@
+//! Some loggers split a flight across sequentially numbered files, e.g. `flight_001.BIN`, `flight_002.BIN`.
+//! Only the first segment starts with the FMT definitions, the others continue right where the previous one
+//! stopped, possibly in the middle of a message. So we read them back to back, as a single stream.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::reader::{FMT_MSG_ID, HEADER_MAGIC};
+
+/// Several files, read as if they were one.
+pub struct SegmentedFile {
+    // file, offset of its first byte in the combined stream, length
+    segments: Vec<(File, u64, u64)>,
+    total_len: u64,
+    pos: u64,
+    current: usize,
+    needs_seek: bool,
+}
+
+impl SegmentedFile {
+    pub fn open(filenames: &[impl AsRef<Path>]) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut total_len = 0;
+
+        for filename in filenames {
+            let filename = filename.as_ref();
+            let file = File::open(filename)
+                .with_context(|| format!("Failed opening file {}", filename.display()))?;
+            let len = file.metadata()?.len();
+            segments.push((file, total_len, len));
+            total_len += len;
+        }
+
+        Ok(Self {
+            segments,
+            total_len,
+            pos: 0,
+            current: 0,
+            needs_seek: false,
+        })
+    }
+}
+
+impl Read for SegmentedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.needs_seek {
+            // the last segment containing `pos`, so that reading at the very end gives EOF from the last file.
+            self.current = self
+                .segments
+                .iter()
+                .rposition(|(_, start, _)| *start <= self.pos)
+                .unwrap_or(0);
+            if let Some((file, start, _)) = self.segments.get_mut(self.current) {
+                file.seek(SeekFrom::Start(self.pos - *start))?;
+            }
+            self.needs_seek = false;
+        }
+
+        while let Some((file, _, _)) = self.segments.get_mut(self.current) {
+            let n = file.read(buf)?;
+            if n > 0 || buf.is_empty() || self.current + 1 == self.segments.len() {
+                self.pos += n as u64;
+                return Ok(n);
+            }
+
+            self.current += 1;
+            self.segments[self.current].0.seek(SeekFrom::Start(0))?;
+        }
+
+        Ok(0)
+    }
+}
+
+impl Seek for SegmentedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(offset) => self.total_len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        self.pos = new_pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        self.needs_seek = true;
+        Ok(self.pos)
+    }
+}
+
+/// A segment continues the previous one if it doesn't start with an FMT packet, as every new log does.
+pub fn is_continuation_segment(filename: impl AsRef<Path>) -> Result<bool> {
+    let mut header = [0u8; 3];
+    let mut file = File::open(filename)?;
+
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(header[..2] != HEADER_MAGIC || header[2] != FMT_MSG_ID),
+        // an empty or tiny file is no continuation of anything
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// `flight_007.BIN` => `flight_008.BIN`, keeping the zero-padding. `None` if the name doesn't end with a number.
+fn next_segment_name(filename: &Path) -> Option<PathBuf> {
+    let stem = filename.file_stem()?.to_str()?;
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return None;
+    }
+
+    let (prefix, number) = stem.split_at(stem.len() - digits);
+    let next = number.parse::<u64>().ok()?.checked_add(1)?;
+
+    let mut name = format!("{}{:0width$}", prefix, next, width = digits);
+    if let Some(ext) = filename.extension().and_then(|e| e.to_str()) {
+        name = format!("{}.{}", name, ext);
+    }
+    Some(filename.with_file_name(name))
+}
+
+/// The sibling segments that continue `filename`, in order, not including `filename` itself.
+pub fn find_continuation_segments(filename: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let mut segments = Vec::new();
+    let mut current = filename.as_ref().to_path_buf();
+
+    while let Some(next) = next_segment_name(&current) {
+        if !next.is_file() || !is_continuation_segment(&next)? {
+            break;
+        }
+        segments.push(next.clone());
+        current = next;
+    }
+
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        reader::{ArduFrame, ArduReader, LogValue},
+        testing::LogBuilder,
+    };
+    use std::{env, fs};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("arducap-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_all(mut reader: ArduReader) -> Vec<String> {
+        let mut frames = Vec::new();
+        loop {
+            match reader.read().unwrap() {
+                ArduFrame::Eof => return frames,
+                ArduFrame::ArduDefinition(d) => frames.push(format!("def {}", d.ardu_fmt.name)),
+                ArduFrame::ArduMessage(m) => {
+                    frames.push(format!("msg {} {:?}", m.current_ts, m.values))
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_next_segment_name() {
+        let next = |name: &str| next_segment_name(Path::new(name)).map(|p| p.display().to_string());
+
+        assert_eq!(
+            next("logs/flight_007.BIN"),
+            Some("logs/flight_008.BIN".to_string())
+        );
+        assert_eq!(next("00000099.bin"), Some("00000100.bin".to_string()));
+        assert_eq!(next("9.BIN"), Some("10.BIN".to_string()));
+        assert_eq!(next("flight.BIN"), None);
+    }
+
+    #[test]
+    fn test_segments_read_as_one_log() {
+        let mut log = LogBuilder::new();
+        log.definition(10, "TST", "Qi", "TimeUS,Value");
+        for i in 0..100u64 {
+            log.message(10, &[LogValue::UInt(1000 + i), LogValue::Int(i as i64)]);
+        }
+        let data = log.build();
+
+        // split mid-message, twice, and add an unrelated log after that.
+        let dir = temp_dir("segments");
+        let paths: Vec<PathBuf> = (1..=4)
+            .map(|i| dir.join(format!("flight_{:03}.BIN", i)))
+            .collect();
+        fs::write(&paths[0], &data[..501]).unwrap();
+        fs::write(&paths[1], &data[501..1002]).unwrap();
+        fs::write(&paths[2], &data[1002..]).unwrap();
+        fs::write(&paths[3], &data).unwrap();
+
+        assert!(!is_continuation_segment(&paths[0]).unwrap());
+        assert!(is_continuation_segment(&paths[1]).unwrap());
+        assert!(!is_continuation_segment(&paths[3]).unwrap());
+        assert_eq!(
+            find_continuation_segments(&paths[0]).unwrap(),
+            paths[1..3].to_vec()
+        );
+        assert!(find_continuation_segments(&paths[3]).unwrap().is_empty());
+
+        let expected = read_all(ArduReader::from_reader(io::Cursor::new(&data)));
+        let segmented = SegmentedFile::open(&paths[..3]).unwrap();
+        assert_eq!(read_all(ArduReader::from_reader(segmented)), expected);
+
+        let mut segmented = SegmentedFile::open(&paths[..3]).unwrap();
+        assert_eq!(segmented.seek(SeekFrom::End(0)).unwrap(), data.len() as u64);
+        segmented.seek(SeekFrom::Start(499)).unwrap();
+        let mut buf = [0u8; 4];
+        segmented.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[499..503]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::{
+    parallel::{default_jobs, read_file_parallel, read_frames_parallel},
+    reader::{ArduFrame, ArduMessage, LogValue},
+};
+
+/// The values of one field, across all messages of a type, stored in the type they were logged with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Int(Vec<i64>),
+    UInt(Vec<u64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    Str(Vec<String>),
+}
+
+impl Column {
+    // A column of the type `value` was logged with, padded to `len` rows, for when the label shows up late.
+    fn padded_for(value: &LogValue, len: usize) -> Self {
+        match value {
+            LogValue::Int(_) => Column::Int(vec![0; len]),
+            LogValue::UInt(_) => Column::UInt(vec![0; len]),
+            LogValue::Float(_) => Column::Float(vec![f32::NAN; len]),
+            LogValue::Double(_) => Column::Double(vec![f64::NAN; len]),
+            LogValue::Str(_) => Column::Str(vec![String::new(); len]),
+        }
+    }
+
+    // A message type can be redefined mid-log; values that don't match the column type are converted as well as possible.
+    fn push(&mut self, value: Option<LogValue>) {
+        match self {
+            Column::Int(c) => c.push(value.and_then(|v| v.as_i64()).unwrap_or(0)),
+            Column::UInt(c) => c.push(value.and_then(|v| v.as_u64()).unwrap_or(0)),
+            Column::Float(c) => c.push(value.and_then(|v| v.as_f64()).unwrap_or(f64::NAN) as f32),
+            Column::Double(c) => c.push(value.and_then(|v| v.as_f64()).unwrap_or(f64::NAN)),
+            Column::Str(c) => c.push(match value {
+                Some(LogValue::Str(s)) => s,
+                Some(other) => other.to_string(),
+                None => String::new(),
+            }),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Column::Int(c) => c.len(),
+            Column::UInt(c) => c.len(),
+            Column::Float(c) => c.len(),
+            Column::Double(c) => c.len(),
+            Column::Str(c) => c.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_i64(&self) -> Option<&[i64]> {
+        match self {
+            Column::Int(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<&[u64]> {
+        match self {
+            Column::UInt(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<&[f32]> {
+        match self {
+            Column::Float(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<&[f64]> {
+        match self {
+            Column::Double(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&[String]> {
+        match self {
+            Column::Str(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Any numeric column, widened to f64. `None` for string columns.
+    pub fn to_f64_vec(&self) -> Option<Vec<f64>> {
+        match self {
+            Column::Int(c) => Some(c.iter().map(|&v| v as f64).collect()),
+            Column::UInt(c) => Some(c.iter().map(|&v| v as f64).collect()),
+            Column::Float(c) => Some(c.iter().map(|&v| v as f64).collect()),
+            Column::Double(c) => Some(c.clone()),
+            Column::Str(_) => None,
+        }
+    }
+}
+
+/// All messages of one type, column by column. Row `i` of every column belongs to `timestamps[i]` (nanoseconds).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub name: String,
+    pub timestamps: Vec<u64>,
+    pub columns: Vec<(String, Column)>,
+}
+
+impl Table {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            timestamps: Vec::new(),
+            columns: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, message: ArduMessage) {
+        let mut values: Vec<Option<LogValue>> = message.values.into_iter().map(Some).collect();
+
+        for (label, column) in self.columns.iter_mut() {
+            let value = message
+                .labels
+                .iter()
+                .zip(values.iter_mut())
+                .find(|(l, v)| *l == label && v.is_some())
+                .and_then(|(_, v)| v.take());
+            column.push(value);
+        }
+
+        // labels we haven't seen before: either the first row, or the type got redefined with more fields.
+        for (label, value) in message.labels.iter().zip(values) {
+            if let Some(value) = value {
+                let mut column = Column::padded_for(&value, self.timestamps.len());
+                column.push(Some(value));
+                self.columns.push((label.clone(), column));
+            }
+        }
+
+        self.timestamps.push(message.current_ts);
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(|(label, _)| label.as_str())
+    }
+
+    pub fn column(&self, label: &str) -> Option<&Column> {
+        self.columns
+            .iter()
+            .find(|(l, _)| l == label)
+            .map(|(_, column)| column)
+    }
+}
+
+/// A whole log, decoded into one `Table` per message type. Meant for analysis code, e.g.
+///
+/// ```no_run
+/// # fn main() -> anyhow::Result<()> {
+/// let log = arducap::table::ArduLog::load("00000042.BIN")?;
+/// if let Some(gps) = log.table("GPS") {
+///     let altitudes = gps.column("Alt").and_then(|c| c.to_f64_vec());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct ArduLog {
+    tables: HashMap<String, Table>,
+    type_names: HashMap<u8, String>,
+}
+
+impl ArduLog {
+    fn new() -> Self {
+        Self {
+            tables: HashMap::new(),
+            type_names: HashMap::new(),
+        }
+    }
+
+    fn push_frame(&mut self, frame: ArduFrame) {
+        match frame {
+            ArduFrame::ArduDefinition(definition) => {
+                self.type_names
+                    .insert(definition.ardu_fmt.type_id, definition.ardu_fmt.name);
+            }
+            ArduFrame::ArduMessage(message) => {
+                // the reader only emits messages it has a definition for, so the name is always known.
+                if let Some(name) = self.type_names.get(&message.type_id) {
+                    self.tables
+                        .entry(name.clone())
+                        .or_insert_with(|| Table::new(name))
+                        .push(message);
+                }
+            }
+            ArduFrame::Eof => {}
+        }
+    }
+
+    pub fn load(filename: &str) -> Result<Self> {
+        let mut log = Self::new();
+        read_file_parallel(filename, default_jobs(), |frame| {
+            log.push_frame(frame);
+            Ok(())
+        })?;
+        Ok(log)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut log = Self::new();
+        read_frames_parallel(data, default_jobs(), |frame| {
+            log.push_frame(frame);
+            Ok(())
+        })?;
+        Ok(log)
+    }
+
+    /// `None` if the log has no messages of that type.
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(name)
+    }
+
+    pub fn table_names(&self) -> impl Iterator<Item = &str> {
+        self.tables.keys().map(|name| name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::LogBuilder;
+
+    #[test]
+    fn test_table() {
+        let mut log = LogBuilder::new();
+        log.definition(20, "GPS", "QLf", "TimeUS,Lat,Alt");
+        for i in 0..3 {
+            log.message(
+                20,
+                &[
+                    LogValue::UInt(1000 * (i + 1)),
+                    LogValue::Int(473977420 + i as i64),
+                    LogValue::Float(100.5 + i as f32),
+                ],
+            );
+        }
+
+        let log = ArduLog::from_bytes(&log.build()).unwrap();
+        assert_eq!(log.table_names().collect::<Vec<_>>(), vec!["GPS"]);
+        assert!(log.table("ATT").is_none());
+
+        let gps = log.table("GPS").unwrap();
+        assert_eq!(gps.len(), 3);
+        assert_eq!(gps.timestamps, vec![1_000_000, 2_000_000, 3_000_000]);
+        assert_eq!(
+            gps.labels().collect::<Vec<_>>(),
+            vec!["TimeUS", "Lat", "Alt"]
+        );
+        assert_eq!(
+            gps.column("Lat").unwrap().as_i64(),
+            Some(&[473977420, 473977421, 473977422][..])
+        );
+        assert_eq!(
+            gps.column("Alt").unwrap().as_f32(),
+            Some(&[100.5, 101.5, 102.5][..])
+        );
+        assert_eq!(
+            gps.column("TimeUS").unwrap().to_f64_vec(),
+            Some(vec![1000.0, 2000.0, 3000.0])
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Helpers for testing transformers against this crate: build small logs in memory, run them through the
+//! pipeline into an in-memory MCAP, and compare what comes out against golden files.
+//!
+//! ```
+//! use arducap::{reader::LogValue, testing::*, transformers::GenericTransformer};
+//!
+//! let log = LogBuilder::new()
+//!     .definition(10, "BARO", "Qf", "TimeUS,Alt")
+//!     .message(10, &[LogValue::UInt(1000), LogValue::Float(12.5)])
+//!     .build();
+//!
+//! let mcap = convert_to_mcap(&log, vec![Box::new(GenericTransformer::new())]).unwrap();
+//! let messages = read_mcap_messages(&mcap).unwrap();
+//!
+//! assert_eq!(messages[0].topic, "/ardupilot/BARO");
+//! assert_eq!(messages[0].payload["Alt"], 12.5);
+//! ```
+
+use std::{collections::HashMap, env, fs, io::Cursor, path::Path};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    pipeline::McapPipeline,
+    reader::{field_length, ArduReader, LogValue, FMT_MSG_ID, HEADER_LEN, HEADER_MAGIC},
+    transformers::Transformer,
+};
+
+/// Builds a Dataflash log in memory, one packet at a time.
+/// Misuse (e.g. a value that doesn't fit its format char) panics, as it's a bug in the test itself.
+pub struct LogBuilder {
+    data: Vec<u8>,
+    formats: HashMap<u8, String>,
+}
+
+impl LogBuilder {
+    /// Starts with the FMT definition of FMT itself, like every log written by ArduPilot does.
+    pub fn new() -> Self {
+        let mut builder = Self {
+            data: Vec::new(),
+            formats: HashMap::new(),
+        };
+        builder.definition(
+            FMT_MSG_ID,
+            "FMT",
+            "BBnNZ",
+            "Type,Length,Name,Format,Columns",
+        );
+        builder
+    }
+
+    pub fn definition(&mut self, type_id: u8, name: &str, format: &str, labels: &str) -> &mut Self {
+        let length = format
+            .chars()
+            .map(|c| field_length(c).unwrap())
+            .sum::<u64>()
+            + HEADER_LEN;
+
+        self.data.extend(HEADER_MAGIC);
+        self.data.push(FMT_MSG_ID);
+        self.data.push(type_id);
+        self.data.push(length as u8);
+        self.data.extend(padded(name, 4));
+        self.data.extend(padded(format, 16));
+        self.data.extend(padded(labels, 64));
+
+        self.formats.insert(type_id, format.to_string());
+        self
+    }
+
+    /// `values` must match the format string of the `type_id` definition, in order and count.
+    pub fn message(&mut self, type_id: u8, values: &[LogValue]) -> &mut Self {
+        let format = self
+            .formats
+            .get(&type_id)
+            .unwrap_or_else(|| panic!("no definition for type {}", type_id));
+        assert_eq!(
+            format.len(),
+            values.len(),
+            "format {} doesn't match the number of values",
+            format
+        );
+
+        self.data.extend(HEADER_MAGIC);
+        self.data.push(type_id);
+        for (c, value) in format.chars().zip(values) {
+            encode_value(&mut self.data, c, value);
+        }
+        self
+    }
+
+    /// Appends arbitrary bytes, e.g. to simulate a truncated or corrupted log.
+    pub fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.data.extend(bytes);
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+}
+
+impl Default for LogBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn padded(s: &str, len: usize) -> Vec<u8> {
+    assert!(s.len() <= len, "{} doesn't fit in {} bytes", s, len);
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.resize(len, 0);
+    bytes
+}
+
+fn encode_value(data: &mut Vec<u8>, c: char, value: &LogValue) {
+    let int = || {
+        value
+            .as_i64()
+            .unwrap_or_else(|| panic!("{:?} is not an integer", value))
+    };
+    let uint = || {
+        value
+            .as_u64()
+            .unwrap_or_else(|| panic!("{:?} is not an unsigned integer", value))
+    };
+    let float = || {
+        value
+            .as_f64()
+            .unwrap_or_else(|| panic!("{:?} is not a number", value))
+    };
+    let string = |len| {
+        padded(
+            value
+                .as_str()
+                .unwrap_or_else(|| panic!("{:?} is not a string", value)),
+            len,
+        )
+    };
+
+    match c {
+        'b' => data.extend(i8::try_from(int()).unwrap().to_le_bytes()),
+        'h' | 'c' => data.extend(i16::try_from(int()).unwrap().to_le_bytes()),
+        'i' | 'L' | 'e' => data.extend(i32::try_from(int()).unwrap().to_le_bytes()),
+        'q' => data.extend(int().to_le_bytes()),
+        'B' | 'M' => data.extend(u8::try_from(uint()).unwrap().to_le_bytes()),
+        'H' | 'C' => data.extend(u16::try_from(uint()).unwrap().to_le_bytes()),
+        'I' | 'E' => data.extend(u32::try_from(uint()).unwrap().to_le_bytes()),
+        'Q' => data.extend(uint().to_le_bytes()),
+        'f' => data.extend((float() as f32).to_le_bytes()),
+        'd' => data.extend(float().to_le_bytes()),
+        'n' => data.extend(string(4)),
+        'N' => data.extend(string(16)),
+        'Z' => data.extend(string(64)),
+        _ => panic!("unexpected format char: {}", c),
+    }
+}
+
+/// Runs an in-memory log through the given transformers, returning the MCAP file contents.
+pub fn convert_to_mcap(log: &[u8], transformers: Vec<Box<dyn Transformer>>) -> Result<Vec<u8>> {
+    let mut reader = ArduReader::from_reader(Cursor::new(log));
+    let mut pipeline = McapPipeline::new(Cursor::new(Vec::new()), transformers)?;
+
+    while !pipeline.process_frame(reader.read()?)? {}
+
+    Ok(pipeline.into_inner().into_inner())
+}
+
+/// An MCAP message, with its JSON payload decoded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McapMessage {
+    pub topic: String,
+    pub schema_name: String,
+    pub log_time: u64,
+    pub payload: Value,
+}
+
+/// All messages of an MCAP file, in the order they were written.
+pub fn read_mcap_messages(mcap: &[u8]) -> Result<Vec<McapMessage>> {
+    let mut messages = Vec::new();
+
+    for message in mcap::MessageStream::new(mcap)? {
+        let message = message?;
+        messages.push(McapMessage {
+            topic: message.channel.topic.clone(),
+            schema_name: message
+                .channel
+                .schema
+                .as_ref()
+                .map(|s| s.name.clone())
+                .unwrap_or_default(),
+            log_time: message.log_time,
+            payload: serde_json::from_slice(&message.data)?,
+        });
+    }
+
+    Ok(messages)
+}
+
+// Golden files are JSON, and serde_json doesn't round-trip floats to the last bit,
+// so numbers only need to agree to a relative 1e-9.
+fn json_approx_eq(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Number(e), Value::Number(a)) => match (e.as_f64(), a.as_f64()) {
+            (Some(e), Some(a)) => (e - a).abs() <= 1e-9 * e.abs().max(a.abs()).max(1.0),
+            _ => e == a,
+        },
+        (Value::Array(e), Value::Array(a)) => {
+            e.len() == a.len() && e.iter().zip(a).all(|(e, a)| json_approx_eq(e, a))
+        }
+        (Value::Object(e), Value::Object(a)) => {
+            e.len() == a.len()
+                && e.iter()
+                    .all(|(k, e)| a.get(k).is_some_and(|a| json_approx_eq(e, a)))
+        }
+        _ => expected == actual,
+    }
+}
+
+/// Compares `messages` with the golden file at `path`, panicking on the first difference.
+/// Numbers are compared with a small relative tolerance.
+///
+/// With `ARDUCAP_UPDATE_GOLDEN` set, the golden file is (re)written instead; review the result before committing it.
+/// A missing golden file is an error otherwise, so that a wrong path can't pass silently.
+pub fn assert_golden(path: impl AsRef<Path>, messages: &[McapMessage]) {
+    let path = path.as_ref();
+
+    if env::var_os("ARDUCAP_UPDATE_GOLDEN").is_some() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, serde_json::to_string_pretty(messages).unwrap() + "\n").unwrap();
+        eprintln!("wrote golden file {}", path.display());
+        return;
+    }
+
+    assert!(
+        path.exists(),
+        "golden file {} doesn't exist (set ARDUCAP_UPDATE_GOLDEN=1 to create it)",
+        path.display()
+    );
+
+    let golden: Vec<McapMessage> = serde_json::from_slice(&fs::read(path).unwrap())
+        .unwrap_or_else(|e| panic!("{} is not a valid golden file: {}", path.display(), e));
+
+    for (idx, (expected, actual)) in golden.iter().zip(messages).enumerate() {
+        let matches = expected.topic == actual.topic
+            && expected.schema_name == actual.schema_name
+            && expected.log_time == actual.log_time
+            && json_approx_eq(&expected.payload, &actual.payload);
+
+        assert!(
+            matches,
+            "message {} differs from {} (set ARDUCAP_UPDATE_GOLDEN=1 to update it)\n expected: {:?}\n   actual: {:?}",
+            idx,
+            path.display(),
+            expected,
+            actual
+        );
+    }
+    assert_eq!(
+        golden.len(),
+        messages.len(),
+        "message count differs from {} (set ARDUCAP_UPDATE_GOLDEN=1 to update it)",
+        path.display()
+    );
+}
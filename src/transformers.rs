@@ -1,4 +1,4 @@
-use crate::reader::{ArduDefinition, ArduMessage, FmtPacket};
+use crate::reader::{ArduDefinition, ArduMessage, FmtPacket, LogValue};
 use anyhow::Result;
 use serde_json::{json, Map};
 use std::collections::HashMap;
@@ -45,6 +45,12 @@ impl GenericTransformer {
     }
 }
 
+impl Default for GenericTransformer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Transformer for GenericTransformer {
     fn check_registered_to_transform(&mut self, definition: &ArduDefinition) -> bool {
         let schema_str = generate_json_schema(&definition.ardu_fmt, &definition.labels);
@@ -64,7 +70,7 @@ impl Transformer for GenericTransformer {
             schema_name: name.clone(),
             schema_encoding: "jsonschema".to_string(),
             schema_data: schema_bytes.clone(),
-            payload: serde_json::to_vec(&msg.json_obj)?,
+            payload: serde_json::to_vec(&msg.to_json())?,
         }])
     }
 }
@@ -120,6 +126,12 @@ impl FoxgloveFusedTransformer {
     }
 }
 
+impl Default for FoxgloveFusedTransformer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 fn euler_to_quat(roll_cd: f64, pitch_cd: f64, yaw_cd: f64) -> (f64, f64, f64, f64) {
     // 1. Convert Centi-degrees to Radians
     let r = (roll_cd / 100.0).to_radians();
@@ -151,9 +163,26 @@ fn euler_to_quat(roll_cd: f64, pitch_cd: f64, yaw_cd: f64) -> (f64, f64, f64, f6
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
     use super::*;
     use approx::assert_relative_eq;
+    use serde_json::Value;
+
+    // integers become LogValue::Int, floats LogValue::Double, just like the reader would decode 'i' and 'd' fields.
+    fn message_from_json(type_id: u8, current_ts: u64, fields: Value) -> ArduMessage {
+        let fields = fields.as_object().unwrap();
+        let labels = fields.keys().cloned().collect();
+        let values = fields
+            .values()
+            .map(|v| match v.as_i64() {
+                Some(i) => LogValue::Int(i),
+                None => LogValue::Double(v.as_f64().unwrap()),
+            })
+            .collect();
+
+        ArduMessage::new(type_id, current_ts, labels, values)
+    }
 
     #[test]
     fn test_euler_to_quat_ned_to_enu() {
@@ -187,6 +216,124 @@ mod tests {
         // for those who don't believe Pythagoras, nevermind simple algebra
         assert_relative_eq!(x * x + y * y + z * z + w * w, 1.0);
     }
+
+    #[test]
+    fn test_decode_internal_errors() {
+        assert!(decode_internal_errors(0).is_empty());
+
+        // watchdog_reset | main_loop_stuck
+        let errors = decode_internal_errors((1 << 11) | (1 << 15));
+        assert_eq!(errors, vec!["watchdog_reset", "main_loop_stuck"]);
+
+        assert_eq!(decode_internal_errors(1 << 31), vec!["unknown_bit_31"]);
+    }
+
+    #[test]
+    fn test_decode_fault() {
+        assert_eq!(decode_fault_type(3), "HardFault");
+        assert_eq!(decode_fault_type(42), "Unknown(42)");
+
+        assert_eq!(decode_active_exception(0x0000_0000), "Thread");
+        assert_eq!(decode_active_exception(0x0040_0803), "HardFault");
+        assert_eq!(decode_active_exception(0x0000_0010 + 37), "IRQ37");
+        assert_eq!(decode_active_exception(12), "DebugMonitor");
+        assert_eq!(decode_active_exception(7), "Reserved(7)");
+        assert_eq!(decode_active_exception(13), "Reserved(13)");
+    }
+
+    #[test]
+    fn test_height_above_terrain_source_selection() {
+        let mut t = TerrainAltitudeTransformer::new();
+        t.topic_map.insert(1, POS.to_string());
+        t.topic_map.insert(2, TERR.to_string());
+        t.topic_map.insert(3, RFND.to_string());
+
+        let msg =
+            |type_id, current_ts, fields: Value| message_from_json(type_id, current_ts, fields);
+        let height = |out: &[TransformedMessage]| -> Value {
+            serde_json::from_slice(&out[0].payload).unwrap()
+        };
+
+        // nothing to compare against yet
+        let pos = msg(1, 1_000_000_000, json!({"Alt": 150.0}));
+        assert!(t.transform(&pos).unwrap().is_empty());
+
+        t.transform(&msg(2, 1_000_000_000, json!({"Status": 2, "TerrH": 100.0})))
+            .unwrap();
+        let out = height(&t.transform(&pos).unwrap());
+        assert_eq!(out["source"], "terrain_database");
+        assert_relative_eq!(out["height"].as_f64().unwrap(), 50.0);
+
+        // a good downward rangefinder reading wins over the terrain database...
+        t.transform(&msg(
+            3,
+            1_000_000_000,
+            json!({"Stat": 4, "Orient": 25, "Dist": 48.5}),
+        ))
+        .unwrap();
+        let out = height(&t.transform(&pos).unwrap());
+        assert_eq!(out["source"], "rangefinder");
+        assert_relative_eq!(out["height"].as_f64().unwrap(), 48.5);
+
+        // ...until it goes stale.
+        let later_pos = msg(1, 2_000_000_000, json!({"Alt": 150.0}));
+        let out = height(&t.transform(&later_pos).unwrap());
+        assert_eq!(out["source"], "terrain_database");
+
+        // once the terrain database turns unhealthy, its last height is dropped.
+        t.transform(&msg(2, 2_000_000_000, json!({"Status": 1, "TerrH": 100.0})))
+            .unwrap();
+        assert!(t.transform(&later_pos).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_wrap_180() {
+        assert_relative_eq!(wrap_180(0.0), 0.0);
+        assert_relative_eq!(wrap_180(358.0), -2.0);
+        assert_relative_eq!(wrap_180(-358.0), 2.0);
+        assert_relative_eq!(wrap_180(180.0), -180.0);
+    }
+
+    #[test]
+    fn test_sim_comparison() {
+        let mut t = SimComparisonTransformer::new();
+        t.topic_map.insert(1, SIM.to_string());
+        t.topic_map.insert(2, ATT.to_string());
+        t.topic_map.insert(3, POS.to_string());
+
+        let msg = |type_id, fields: Value| message_from_json(type_id, 1_000_000_000, fields);
+        let sim = msg(
+            1,
+            json!({"Roll": 100, "Pitch": -50, "Yaw": 35900, "Alt": 100.0, "Lat": 473977420, "Lng": 85455940}),
+        );
+
+        // no estimate yet, nothing to compare
+        assert!(t.transform(&sim).unwrap().is_empty());
+
+        t.transform(&msg(2, json!({"Roll": 150, "Pitch": -50, "Yaw": 100})))
+            .unwrap();
+        t.transform(&msg(
+            3,
+            json!({"Lat": 47.397742, "Lng": 8.545594, "Alt": 101.5}),
+        ))
+        .unwrap();
+
+        let out = t.transform(&sim).unwrap();
+        assert_eq!(out.len(), 2);
+
+        let pos: Value = serde_json::from_slice(&out[0].payload).unwrap();
+        assert_relative_eq!(pos["error"]["up"].as_f64().unwrap(), 1.5, epsilon = 1e-6);
+        assert_relative_eq!(
+            pos["error"]["horizontal"].as_f64().unwrap(),
+            0.0,
+            epsilon = 1e-6
+        );
+
+        let att: Value = serde_json::from_slice(&out[1].payload).unwrap();
+        assert_relative_eq!(att["error"]["roll"].as_f64().unwrap(), 0.5);
+        assert_relative_eq!(att["error"]["pitch"].as_f64().unwrap(), 0.0);
+        assert_relative_eq!(att["error"]["yaw"].as_f64().unwrap(), 2.0);
+    }
 }
 
 // We must account for earth curvature in our ENU calculations
@@ -260,7 +407,6 @@ impl Transformer for FoxgloveFusedTransformer {
 
     fn transform(&mut self, msg: &ArduMessage) -> Result<Vec<TransformedMessage>> {
         let mut output = Vec::new();
-        let json = &msg.json_obj;
 
         // this unwrap should never fail, unless there's a critical bug in the caller pipeline.
         let topic_name = self.topic_map.get(&msg.type_id).unwrap();
@@ -278,8 +424,8 @@ impl Transformer for FoxgloveFusedTransformer {
         let has_att = topic_name == ATT;
 
         if has_position {
-            let get_int = |k| json.get(k).and_then(|v| v.as_i64());
-            let get_flt = |k| json.get(k).and_then(|v| v.as_f64());
+            let get_int = |k| msg.get_i64(k);
+            let get_flt = |k| msg.get_f64(k);
 
             let lat = get_int("Lat").or(get_int("Latitude")).unwrap_or(0) as f64 / 1.0e7;
             let lon = get_int("Lng").or(get_int("Longitude")).unwrap_or(0) as f64 / 1.0e7;
@@ -326,7 +472,7 @@ impl Transformer for FoxgloveFusedTransformer {
         }
 
         if has_att {
-            let get_flt = |k| json.get(k).and_then(|v| v.as_f64()).unwrap_or(0.0);
+            let get_flt = |k| msg.get_f64(k).unwrap_or(0.0);
             self.current_att = (get_flt("Roll"), get_flt("Pitch"), get_flt("Yaw"));
         }
 
@@ -365,3 +511,524 @@ impl Transformer for FoxgloveFusedTransformer {
         Ok(output)
     }
 }
+
+// Watchdog resets and internal errors are the reason most people dig a log out in the first place,
+// so we surface them on a dedicated topic rather than leaving them buried in /ardupilot/WDOG and /ardupilot/PM.
+// Field meanings and the bit/enum tables below follow ArduPilot's AP_InternalError and AP_HAL_ChibiOS.
+
+const CRASH_EVENT_SCHEMA: &str = r#"{
+  "type": "object",
+  "title": "arducap.CrashEvent",
+  "properties": {
+    "source": { "type": "string" },
+    "reason": { "type": "string" },
+    "internal_errors": { "type": "array", "items": { "type": "string" } },
+    "internal_error_mask": { "type": "integer" },
+    "internal_error_count": { "type": "integer" },
+    "internal_error_line": { "type": "integer" },
+    "fault_type": { "type": "string" },
+    "fault_address": { "type": "string" },
+    "fault_line": { "type": "integer" },
+    "fault_thread_priority": { "type": "integer" },
+    "active_exception": { "type": "string" },
+    "icsr": { "type": "string" },
+    "link_register": { "type": "string" },
+    "thread_name": { "type": "string" },
+    "scheduler_task": { "type": "integer" },
+    "mavlink_msg": { "type": "integer" },
+    "mavlink_cmd": { "type": "integer" },
+    "semaphore_line": { "type": "integer" }
+  }
+}"#;
+
+// AP_InternalError::error_t, indexed by bit position.
+const INTERNAL_ERROR_NAMES: [&str; 30] = [
+    "logger_mapfailure",
+    "logger_missing_logstructure",
+    "logger_logwrite_missingfmt",
+    "logger_too_many_deletions",
+    "logger_bad_getfilename",
+    "panic",
+    "logger_flushing_without_sem",
+    "logger_bad_current_block",
+    "logger_blockcount_mismatch",
+    "logger_dequeue_failure",
+    "constraining_nan",
+    "watchdog_reset",
+    "iomcu_reset",
+    "iomcu_fail",
+    "spi_fail",
+    "main_loop_stuck",
+    "gcs_bad_missionprotocol_link",
+    "bitmask_range",
+    "gcs_offset",
+    "i2c_isr",
+    "flow_of_control",
+    "switch_full_sector_recursion",
+    "bad_rotation",
+    "stack_overflow",
+    "imu_reset",
+    "gpio_isr",
+    "mem_guard",
+    "dma_fail",
+    "params_restored",
+    "invalid_arg_or_result",
+];
+
+fn decode_internal_errors(mask: u64) -> Vec<String> {
+    (0..64)
+        .filter(|bit| mask & (1 << bit) != 0)
+        .map(|bit| match INTERNAL_ERROR_NAMES.get(bit) {
+            Some(name) => name.to_string(),
+            None => format!("unknown_bit_{}", bit),
+        })
+        .collect()
+}
+
+// ChibiOS FaultType, as stored in WDOG.FT. Cortex-M exception numbers happen to line up with it.
+fn decode_fault_type(fault_type: u64) -> String {
+    match fault_type {
+        0 => "None".to_string(),
+        1 => "Reset".to_string(),
+        2 => "NMI".to_string(),
+        3 => "HardFault".to_string(),
+        4 => "MemManage".to_string(),
+        5 => "BusFault".to_string(),
+        6 => "UsageFault".to_string(),
+        other => format!("Unknown({})", other),
+    }
+}
+
+// ICSR.VECTACTIVE (bits 8:0) holds the exception number that was executing when the watchdog fired.
+fn decode_active_exception(icsr: u64) -> String {
+    match icsr & 0x1FF {
+        0 => "Thread".to_string(),
+        11 => "SVCall".to_string(),
+        14 => "PendSV".to_string(),
+        15 => "SysTick".to_string(),
+        n @ 1..=6 => decode_fault_type(n),
+        12 => "DebugMonitor".to_string(),
+        n @ 16.. => format!("IRQ{}", n - 16),
+        n => format!("Reserved({})", n),
+    }
+}
+
+const WDOG: &str = "WDOG";
+const PM: &str = "PM";
+
+pub struct CrashEventTransformer {
+    topic_map: HashMap<u8, String>,
+    last_internal_errors: u64,
+}
+
+impl CrashEventTransformer {
+    pub fn new() -> Self {
+        Self {
+            topic_map: HashMap::new(),
+            last_internal_errors: 0,
+        }
+    }
+}
+
+impl Default for CrashEventTransformer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transformer for CrashEventTransformer {
+    fn check_registered_to_transform(&mut self, definition: &ArduDefinition) -> bool {
+        let n = &definition.ardu_fmt.name;
+
+        if [WDOG, PM].contains(&n.as_str()) {
+            self.topic_map
+                .insert(definition.ardu_fmt.type_id, n.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn transform(&mut self, msg: &ArduMessage) -> Result<Vec<TransformedMessage>> {
+        let get_uint = |k| msg.get_u64(k);
+        let get_int = |k| msg.get_i64(k);
+        let hex = |v: u64| format!("0x{:08X}", v);
+
+        // this unwrap should never fail, unless there's a critical bug in the caller pipeline.
+        let topic_name = self.topic_map.get(&msg.type_id).unwrap();
+
+        let mut event = if topic_name == WDOG {
+            // WDOG is only logged on the boot following a watchdog reset, so every instance is an event.
+            let mask = get_uint("IE").unwrap_or(0);
+            let fault_type = get_uint("FT").unwrap_or(0);
+            let icsr = get_uint("ICSR").unwrap_or(0);
+
+            json!({
+                "source": WDOG,
+                "reason": "watchdog_reset",
+                "internal_errors": decode_internal_errors(mask),
+                "internal_error_mask": mask,
+                "internal_error_count": get_uint("IEC"),
+                "internal_error_line": get_uint("IEL"),
+                "fault_type": decode_fault_type(fault_type),
+                "fault_address": hex(get_uint("FA").unwrap_or(0)),
+                "fault_line": get_uint("FL"),
+                "fault_thread_priority": get_uint("FP"),
+                "active_exception": decode_active_exception(icsr),
+                "icsr": hex(icsr),
+                "link_register": hex(get_uint("LR").unwrap_or(0)),
+                "thread_name": msg.get_str("TN"),
+                "scheduler_task": get_int("Tsk"),
+                "mavlink_msg": get_uint("MvMsg"),
+                "mavlink_cmd": get_uint("MvCmd"),
+                "semaphore_line": get_uint("SmLn"),
+            })
+        } else {
+            // PM repeats the cumulative internal error mask every second; only report when new bits show up.
+            let mask = get_uint("IntE").unwrap_or(0);
+            let new_bits = mask & !self.last_internal_errors;
+            self.last_internal_errors = mask;
+
+            if new_bits == 0 {
+                return Ok(vec![]);
+            }
+
+            json!({
+                "source": PM,
+                "reason": "internal_error",
+                "internal_errors": decode_internal_errors(new_bits),
+                "internal_error_mask": mask,
+                "internal_error_count": get_uint("ErrC"),
+                "internal_error_line": get_uint("ErrL"),
+            })
+        };
+
+        // fields this firmware doesn't log are left out, rather than breaking the schema types with nulls.
+        if let Some(obj) = event.as_object_mut() {
+            obj.retain(|_, v| !v.is_null());
+        }
+
+        Ok(vec![TransformedMessage {
+            topic: "/events/crash".to_string(),
+            schema_name: "arducap.CrashEvent".to_string(),
+            schema_encoding: "jsonschema".to_string(),
+            schema_data: CRASH_EVENT_SCHEMA.as_bytes().to_vec(),
+            payload: serde_json::to_vec(&event)?,
+        }])
+    }
+}
+
+// Height above terrain is what matters for low-level survey flights, but ArduPilot only logs it sparsely (TERR, ~1Hz)
+// or as raw rangefinder readings. We combine the latest terrain/rangefinder data with every position update,
+// to get a continuous topic.
+
+const HEIGHT_ABOVE_TERRAIN_SCHEMA: &str = r#"{
+  "type": "object",
+  "title": "arducap.HeightAboveTerrain",
+  "properties": {
+    "height": { "type": "number" },
+    "source": { "type": "string" },
+    "altitude_amsl": { "type": "number" },
+    "terrain_height_amsl": { "type": ["number", "null"] }
+  }
+}"#;
+
+const TERR: &str = "TERR";
+const RFND: &str = "RFND";
+
+// TERR.Status: 0 = disabled, 1 = unhealthy, 2 = OK
+const TERRAIN_STATUS_OK: u64 = 2;
+// RFND.Stat: 4 = Good; RFND.Orient: 25 = ROTATION_PITCH_270, i.e. pointing down
+const RANGEFINDER_STATUS_GOOD: u64 = 4;
+const RANGEFINDER_ORIENT_DOWN: u64 = 25;
+// a rangefinder reading older than this is not trusted anymore, we fall back to the terrain database.
+const RANGEFINDER_TIMEOUT_NS: u64 = 500_000_000;
+
+pub struct TerrainAltitudeTransformer {
+    topic_map: HashMap<u8, String>,
+    has_seen_pos: bool,
+    terrain_height: Option<f64>,     // terrain height AMSL, meters
+    rangefinder: Option<(f64, u64)>, // distance (meters), timestamp (ns)
+}
+
+impl TerrainAltitudeTransformer {
+    pub fn new() -> Self {
+        Self {
+            topic_map: HashMap::new(),
+            has_seen_pos: false,
+            terrain_height: None,
+            rangefinder: None,
+        }
+    }
+}
+
+impl Default for TerrainAltitudeTransformer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transformer for TerrainAltitudeTransformer {
+    fn check_registered_to_transform(&mut self, definition: &ArduDefinition) -> bool {
+        let n = &definition.ardu_fmt.name;
+
+        if [GPS, POS, TERR, RFND].contains(&n.as_str()) {
+            self.topic_map
+                .insert(definition.ardu_fmt.type_id, n.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn transform(&mut self, msg: &ArduMessage) -> Result<Vec<TransformedMessage>> {
+        let get_uint = |k| msg.get_u64(k);
+        let get_flt = |k| msg.get_f64(k);
+
+        // this unwrap should never fail, unless there's a critical bug in the caller pipeline.
+        let topic_name = self.topic_map.get(&msg.type_id).unwrap();
+
+        if topic_name == TERR {
+            // an unhealthy or disabled terrain database invalidates the last height, we may have moved on since.
+            self.terrain_height = if get_uint("Status") == Some(TERRAIN_STATUS_OK) {
+                get_flt("TerrH")
+            } else {
+                None
+            };
+            return Ok(vec![]);
+        }
+
+        if topic_name == RFND {
+            let is_good = get_uint("Stat") == Some(RANGEFINDER_STATUS_GOOD);
+            // older firmware doesn't log orientation, and only supports downward rangefinders for this.
+            let is_down = get_uint("Orient").is_none_or(|o| o == RANGEFINDER_ORIENT_DOWN);
+
+            if is_good && is_down {
+                // newer firmware logs Dist as float meters, older as integer centimeters.
+                let distance = match msg.get("Dist") {
+                    Some(v) if v.is_float() => v.as_f64(),
+                    Some(v) => v.as_f64().map(|cm| cm * 0.01),
+                    None => None,
+                };
+                self.rangefinder = distance.map(|d| (d, msg.current_ts));
+            }
+            return Ok(vec![]);
+        }
+
+        // same as FoxgloveFusedTransformer: POS is the better estimate, once we have it, ignore GPS.
+        if topic_name == GPS && self.has_seen_pos {
+            return Ok(vec![]);
+        }
+
+        if topic_name == POS {
+            self.has_seen_pos = true;
+        }
+
+        let altitude_scale_factor = if topic_name == GPS { 0.01 } else { 1.0 };
+        let alt = get_flt("Alt").or(get_flt("Altitude")).unwrap_or(0.0) * altitude_scale_factor;
+
+        let fresh_rangefinder = self
+            .rangefinder
+            .filter(|(_, ts)| msg.current_ts.saturating_sub(*ts) <= RANGEFINDER_TIMEOUT_NS);
+
+        let obj = match (fresh_rangefinder, self.terrain_height) {
+            (Some((distance, _)), terrain_height) => json!({
+                "height": distance,
+                "source": "rangefinder",
+                "altitude_amsl": alt,
+                "terrain_height_amsl": terrain_height,
+            }),
+            (None, Some(terrain_height)) => json!({
+                "height": alt - terrain_height,
+                "source": "terrain_database",
+                "altitude_amsl": alt,
+                "terrain_height_amsl": terrain_height,
+            }),
+            (None, None) => return Ok(vec![]),
+        };
+
+        Ok(vec![TransformedMessage {
+            topic: "/terrain/height_above_terrain".to_string(),
+            schema_name: "arducap.HeightAboveTerrain".to_string(),
+            schema_encoding: "jsonschema".to_string(),
+            schema_data: HEIGHT_ABOVE_TERRAIN_SCHEMA.as_bytes().to_vec(),
+            payload: serde_json::to_vec(&obj)?,
+        }])
+    }
+}
+
+// SITL logs carry the simulator's ground truth in SIM. Comparing it against the EKF outputs (POS, ATT)
+// makes estimator and controller regressions visible at a glance, instead of eyeballing two plots.
+
+const SIM_POSITION_SCHEMA: &str = r#"{
+  "type": "object",
+  "title": "arducap.SimPositionComparison",
+  "properties": {
+    "truth": {
+      "type": "object",
+      "properties": { "latitude": {"type":"number"}, "longitude": {"type":"number"}, "altitude": {"type":"number"} }
+    },
+    "estimate": {
+      "type": "object",
+      "properties": { "latitude": {"type":"number"}, "longitude": {"type":"number"}, "altitude": {"type":"number"} }
+    },
+    "error": {
+      "type": "object",
+      "properties": {
+        "east": {"type":"number"}, "north": {"type":"number"}, "up": {"type":"number"},
+        "horizontal": {"type":"number"}, "total": {"type":"number"}
+      }
+    }
+  }
+}"#;
+
+const SIM_ATTITUDE_SCHEMA: &str = r#"{
+  "type": "object",
+  "title": "arducap.SimAttitudeComparison",
+  "properties": {
+    "truth": {
+      "type": "object",
+      "properties": { "roll": {"type":"number"}, "pitch": {"type":"number"}, "yaw": {"type":"number"} }
+    },
+    "estimate": {
+      "type": "object",
+      "properties": { "roll": {"type":"number"}, "pitch": {"type":"number"}, "yaw": {"type":"number"} }
+    },
+    "error": {
+      "type": "object",
+      "properties": { "roll": {"type":"number"}, "pitch": {"type":"number"}, "yaw": {"type":"number"} }
+    }
+  }
+}"#;
+
+const SIM: &str = "SIM";
+
+// Angles are logged either as integer centi-degrees ('c'/'C') or float degrees ('f'), depending on firmware version.
+fn angle_deg(v: &LogValue) -> Option<f64> {
+    if v.is_float() {
+        v.as_f64()
+    } else {
+        v.as_f64().map(|cd| cd / 100.0)
+    }
+}
+
+// Likewise, coordinates are either integer 1e-7 degrees ('L') or float/double degrees.
+fn coordinate_deg(v: &LogValue) -> Option<f64> {
+    if v.is_float() {
+        v.as_f64()
+    } else {
+        v.as_f64().map(|c| c / 1.0e7)
+    }
+}
+
+// wraps to [-180, 180), so that e.g. 359° vs 1° is a 2° error, not 358°.
+fn wrap_180(deg: f64) -> f64 {
+    (deg + 180.0).rem_euclid(360.0) - 180.0
+}
+
+pub struct SimComparisonTransformer {
+    topic_map: HashMap<u8, String>,
+    estimated_pos: Option<(f64, f64, f64)>, // Lat, Lon, Alt
+    estimated_att: Option<(f64, f64, f64)>, // Roll, Pitch, Yaw (degrees)
+}
+
+impl SimComparisonTransformer {
+    pub fn new() -> Self {
+        Self {
+            topic_map: HashMap::new(),
+            estimated_pos: None,
+            estimated_att: None,
+        }
+    }
+}
+
+impl Default for SimComparisonTransformer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transformer for SimComparisonTransformer {
+    fn check_registered_to_transform(&mut self, definition: &ArduDefinition) -> bool {
+        let n = &definition.ardu_fmt.name;
+
+        if [SIM, ATT, POS].contains(&n.as_str()) {
+            self.topic_map
+                .insert(definition.ardu_fmt.type_id, n.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn transform(&mut self, msg: &ArduMessage) -> Result<Vec<TransformedMessage>> {
+        let get_angle = |k| msg.get(k).and_then(angle_deg).unwrap_or(0.0);
+        let get_coord = |k| msg.get(k).and_then(coordinate_deg).unwrap_or(0.0);
+        let get_flt = |k| msg.get_f64(k).unwrap_or(0.0);
+
+        // this unwrap should never fail, unless there's a critical bug in the caller pipeline.
+        let topic_name = self.topic_map.get(&msg.type_id).unwrap();
+
+        let lla = || (get_coord("Lat"), get_coord("Lng"), get_flt("Alt"));
+        let rpy = || (get_angle("Roll"), get_angle("Pitch"), get_angle("Yaw"));
+
+        if topic_name == POS {
+            self.estimated_pos = Some(lla());
+            return Ok(vec![]);
+        }
+
+        if topic_name == ATT {
+            self.estimated_att = Some(rpy());
+            return Ok(vec![]);
+        }
+
+        let mut output = Vec::new();
+
+        if let Some((lat, lon, alt)) = self.estimated_pos {
+            let (truth_lat, truth_lon, truth_alt) = lla();
+            let (e, n, u) = wgs84_to_enu(lat, lon, alt, truth_lat, truth_lon, truth_alt);
+
+            let obj = json!({
+                "truth": { "latitude": truth_lat, "longitude": truth_lon, "altitude": truth_alt },
+                "estimate": { "latitude": lat, "longitude": lon, "altitude": alt },
+                "error": {
+                    "east": e,
+                    "north": n,
+                    "up": u,
+                    "horizontal": e.hypot(n),
+                    "total": (e * e + n * n + u * u).sqrt(),
+                },
+            });
+            output.push(TransformedMessage {
+                topic: "/sim/position".to_string(),
+                schema_name: "arducap.SimPositionComparison".to_string(),
+                schema_encoding: "jsonschema".to_string(),
+                schema_data: SIM_POSITION_SCHEMA.as_bytes().to_vec(),
+                payload: serde_json::to_vec(&obj)?,
+            });
+        }
+
+        if let Some((roll, pitch, yaw)) = self.estimated_att {
+            let (truth_roll, truth_pitch, truth_yaw) = rpy();
+
+            let obj = json!({
+                "truth": { "roll": truth_roll, "pitch": truth_pitch, "yaw": truth_yaw },
+                "estimate": { "roll": roll, "pitch": pitch, "yaw": yaw },
+                "error": {
+                    "roll": wrap_180(roll - truth_roll),
+                    "pitch": wrap_180(pitch - truth_pitch),
+                    "yaw": wrap_180(yaw - truth_yaw),
+                },
+            });
+            output.push(TransformedMessage {
+                topic: "/sim/attitude".to_string(),
+                schema_name: "arducap.SimAttitudeComparison".to_string(),
+                schema_encoding: "jsonschema".to_string(),
+                schema_data: SIM_ATTITUDE_SCHEMA.as_bytes().to_vec(),
+                payload: serde_json::to_vec(&obj)?,
+            });
+        }
+
+        Ok(output)
+    }
+}
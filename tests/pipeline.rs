@@ -0,0 +1,101 @@
+use std::{env, fs};
+
+use arducap::{
+    pipeline::{default_transformers, process_ardupilot_segments_with},
+    reader::LogValue::{self, Int, Str, UInt},
+    testing::{assert_golden, convert_to_mcap, read_mcap_messages, LogBuilder},
+};
+
+const GPS: u8 = 10;
+const ATT: u8 = 11;
+const PM: u8 = 12;
+const WDOG: u8 = 13;
+
+fn flight_log() -> Vec<u8> {
+    let mut log = LogBuilder::new();
+    log.definition(GPS, "GPS", "QLLi", "TimeUS,Lat,Lng,Alt")
+        .definition(ATT, "ATT", "Qccc", "TimeUS,Roll,Pitch,Yaw")
+        .definition(PM, "PM", "QIH", "TimeUS,IntE,ErrL")
+        .definition(
+            WDOG,
+            "WDOG",
+            "QbIHHHHHHHIBIIn",
+            "TimeUS,Tsk,IE,IEC,IEL,MvMsg,MvCmd,SmLn,FL,FT,FA,FP,ICSR,LR,TN",
+        );
+
+    let wdog: [LogValue; 15] = [
+        UInt(500_000),
+        Int(-3),
+        UInt(1 << 11),
+        UInt(1),
+        UInt(0),
+        UInt(0),
+        UInt(0),
+        UInt(0),
+        UInt(0),
+        UInt(3),
+        UInt(0x0800_1234),
+        UInt(180),
+        UInt(0x0040_0803),
+        UInt(0x0800_4321),
+        Str("main".to_string()),
+    ];
+    log.message(WDOG, &wdog);
+
+    for i in 0..3u64 {
+        let ts = 1_000_000 * (i + 1);
+        log.message(
+            GPS,
+            &[
+                UInt(ts),
+                Int(473977420 + 100 * i as i64),
+                Int(85455940),
+                Int(48800),
+            ],
+        );
+        log.message(ATT, &[UInt(ts), Int(0), Int(0), Int(4500 * i as i64)]);
+        log.message(
+            PM,
+            &[UInt(ts), UInt(if i == 2 { 1 << 15 } else { 0 }), UInt(0)],
+        );
+    }
+
+    log.build()
+}
+
+#[test]
+fn test_default_pipeline_golden() {
+    let mcap = convert_to_mcap(&flight_log(), default_transformers()).unwrap();
+    let messages = read_mcap_messages(&mcap).unwrap();
+
+    assert_golden(
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/golden/default_pipeline.json"
+        ),
+        &messages,
+    );
+}
+
+#[test]
+fn test_continuation_segment_alone_is_rejected() {
+    let log = flight_log();
+    let dir = env::temp_dir().join(format!("arducap-pipeline-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let first = dir.join("flight_001.BIN");
+    let second = dir.join("flight_002.BIN");
+    fs::write(&first, &log[..300]).unwrap();
+    fs::write(&second, &log[300..]).unwrap();
+    let (first, second) = (first.to_str().unwrap(), second.to_str().unwrap());
+
+    let error =
+        process_ardupilot_segments_with(&[second, first], 1, default_transformers()).unwrap_err();
+    assert!(error.to_string().contains("continues another log"));
+    assert!(!dir.join("flight_002.mcap").exists());
+
+    process_ardupilot_segments_with(&[first, second], 1, default_transformers()).unwrap();
+    assert!(dir.join("flight_001.mcap").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}